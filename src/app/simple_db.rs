@@ -1,26 +1,142 @@
 use std::{
-    io::Error,
+    io::{Error, ErrorKind},
     path::PathBuf,
     sync::{Arc, Mutex},
 };
 
 use crate::{
-    buffer::buffer_manager::BufferManager, file::file_manager::FileManager,
-    log::log_manager::LogManager,
+    buffer::buffer_manager::BufferManager,
+    file::{
+        file_manager::FileManager,
+        options::FileOptions,
+        storage::{fs_backend::FsBackend, memory_backend::MemoryBackend, StorageBackend},
+        superblock::Superblock,
+    },
+    log::{group_commit::GroupCommit, log_manager::LogManager, segments::LogSegments},
+    tx::recovery::point_in_time::{replay_to, RecoveryReport, RecoveryTarget},
 };
+
+const LOG_FILE: &str = "simpledb.log";
+
 pub struct SimpleDB {
     file_manager: Arc<FileManager>,
-    log_manager: Arc<Mutex<LogManager>>,
+    log_manager: Arc<LogManager>,
+    group_commit: Arc<GroupCommit>,
     buffer_manager: Arc<Mutex<BufferManager>>,
 }
 
 impl SimpleDB {
     pub fn new(db_dir: &str, block_size: usize, buffer_size: u16) -> Result<Self, Error> {
-        let file_manager = Arc::new(FileManager::new(PathBuf::from(db_dir), block_size)?);
-        let log_manager = Arc::new(Mutex::new(LogManager::new(
+        Self::with_options(db_dir, block_size, buffer_size, FileOptions::default())
+    }
+
+    pub fn with_options(
+        db_dir: &str,
+        block_size: usize,
+        buffer_size: u16,
+        options: FileOptions,
+    ) -> Result<Self, Error> {
+        let storage = Arc::new(FsBackend::with_options(PathBuf::from(db_dir), &options)?);
+        Self::open(storage, block_size, buffer_size, options)
+    }
+
+    /**
+     * opens an existing database without changing any of its files, e.g. to inspect it.
+     * the block size, the segment size and the log segment size are taken from its superblock;
+     * writes fail with PermissionDenied.
+     */
+    pub fn open_read_only(
+        db_dir: &str,
+        buffer_size: u16,
+        options: FileOptions,
+    ) -> Result<Self, Error> {
+        let options = FileOptions {
+            read_only: true,
+            ..options
+        };
+        let storage = FsBackend::with_options(PathBuf::from(db_dir), &options)?;
+        let superblock = Superblock::read(&storage)?.ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("there is no database in {}", db_dir),
+            )
+        })?;
+        // the control file is never segmented, so it can be read before the segment size is known
+        let options = FileOptions {
+            segment_size: superblock.segment_size(),
+            log_segment_blocks: superblock.log_segment_blocks(),
+            ..options
+        };
+        let storage = Arc::new(FsBackend::with_options(PathBuf::from(db_dir), &options)?);
+        Self::open(storage, superblock.block_size(), buffer_size, options)
+    }
+
+    // a database that lives only in memory, e.g. for tests and benchmarks
+    pub fn new_in_memory(block_size: usize, buffer_size: u16) -> Result<Self, Error> {
+        Self::with_backend(Arc::new(MemoryBackend::new()), block_size, buffer_size)
+    }
+
+    pub fn with_backend(
+        storage: Arc<dyn StorageBackend>,
+        block_size: usize,
+        buffer_size: u16,
+    ) -> Result<Self, Error> {
+        Self::open(storage, block_size, buffer_size, FileOptions::default())
+    }
+
+    pub fn open(
+        storage: Arc<dyn StorageBackend>,
+        block_size: usize,
+        buffer_size: u16,
+        options: FileOptions,
+    ) -> Result<Self, Error> {
+        let file_manager = Arc::new(FileManager::with_options(storage, block_size, &options)?);
+        Self::with_file_manager(file_manager, buffer_size, options)
+    }
+
+    /**
+     * opens the database and brings it back to how it was at the target, see point_in_time.rs.
+     * the storage holds the files restored from a base backup, and the log of the database
+     * from before the backup on, as far as it was not archived; the segments archived to
+     * options.log_archive are brought back first.
+     */
+    pub fn recover_to(
+        storage: Arc<dyn StorageBackend>,
+        block_size: usize,
+        buffer_size: u16,
+        options: FileOptions,
+        target: RecoveryTarget,
+    ) -> Result<(Self, RecoveryReport), Error> {
+        let file_manager = Arc::new(FileManager::with_options(storage, block_size, &options)?);
+        let restored_segments = match &options.log_archive {
+            Some(location) => LogSegments::restore_archived(&file_manager, LOG_FILE, location)?,
+            None => Vec::new(),
+        };
+        let commit_delay = options.commit_delay;
+        let mut db = Self::with_file_manager(file_manager, buffer_size, options)?;
+        let report = replay_to(&db.file_manager, &db.log_manager, target)?;
+        // the replay cut the log back, so the group commit must not trust the old end of it
+        db.group_commit = Arc::new(GroupCommit::new(db.log_manager.clone(), commit_delay));
+        Ok((
+            db,
+            RecoveryReport {
+                restored_segments,
+                ..report
+            },
+        ))
+    }
+
+    fn with_file_manager(
+        file_manager: Arc<FileManager>,
+        buffer_size: u16,
+        options: FileOptions,
+    ) -> Result<Self, Error> {
+        let log_manager = Arc::new(LogManager::with_options(
             file_manager.clone(),
-            "simpledb.log",
-        )?));
+            LOG_FILE,
+            &options,
+        )?);
+        let group_commit = Arc::new(GroupCommit::new(log_manager.clone(), options.commit_delay));
         let buffer_manager = Arc::new(Mutex::new(BufferManager::new(
             file_manager.clone(),
             log_manager.clone(),
@@ -29,16 +145,22 @@ impl SimpleDB {
         Ok(Self {
             file_manager,
             log_manager,
+            group_commit,
             buffer_manager,
         })
     }
     pub fn file_manager(&self) -> Arc<FileManager> {
         self.file_manager.clone()
     }
-    pub fn log_manager(&self) -> Arc<Mutex<LogManager>> {
+    pub fn log_manager(&self) -> Arc<LogManager> {
         self.log_manager.clone()
     }
 
+    // where committing transactions wait for their commit record to reach the disk
+    pub fn group_commit(&self) -> Arc<GroupCommit> {
+        self.group_commit.clone()
+    }
+
     pub fn buffer_manager(&self) -> Arc<Mutex<BufferManager>> {
         self.buffer_manager.clone()
     }
@@ -0,0 +1,110 @@
+use std::{
+    env,
+    io::{self, Write},
+    process,
+};
+
+use simple_db_rust::{
+    app::simple_db::SimpleDB,
+    file::{block_id::BlockId, encryption::EncryptionKey, options::FileOptions},
+    tx::recovery::log_dump::{dump_log, DumpFormat, LogFilter},
+};
+
+// Prints the records of the log of a database, the newest first, without changing any of its
+// files. The database may be in use by another process, records it has not flushed yet are
+// not seen.
+const USAGE: &str = "usage: simpledb-logdump <database directory> [options]
+  --tx <n>                    only records of transaction n
+  --block <file>:<n>          only updates of block n of the file
+  --from-lsn <lsn>            only records at or after the lsn
+  --to-lsn <lsn>              only records at or before the lsn
+  --json                      one JSON object per record instead of text
+  --key <hex>                 the 256 bit key of an encrypted database, as 64 hex digits";
+
+struct Args {
+    db_dir: String,
+    filter: LogFilter,
+    format: DumpFormat,
+    key: Option<EncryptionKey>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let options = FileOptions {
+        encryption_key: args.key,
+        ..FileOptions::default()
+    };
+    let result = SimpleDB::open_read_only(&args.db_dir, 1, options).and_then(|db| {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        dump_log(&db.log_manager(), &args.filter, args.format, &mut out)?;
+        out.flush()
+    });
+    match result {
+        Ok(()) => {}
+        // e.g. the output was piped into head
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("simpledb-logdump: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut db_dir = None;
+    let mut filter = LogFilter::default();
+    let mut format = DumpFormat::Text;
+    let mut key = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--tx" => filter.tx = Some(parse_number(&value()?)?),
+            "--block" => filter.block = Some(parse_block(&value()?)?),
+            "--from-lsn" => filter.from_lsn = Some(parse_number(&value()?)?),
+            "--to-lsn" => filter.to_lsn = Some(parse_number(&value()?)?),
+            "--json" => format = DumpFormat::Json,
+            "--key" => key = Some(parse_key(&value()?)?),
+            "-h" | "--help" => return Err(String::new()),
+            a if a.starts_with('-') => return Err(format!("unknown option {}", a)),
+            _ if db_dir.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => db_dir = Some(arg),
+        }
+    }
+    Ok(Args {
+        db_dir: db_dir.ok_or("no database directory given")?,
+        filter,
+        format,
+        key,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("{} is not a number", s))
+}
+
+// a block is given as <file>:<block number>, the file name may contain colons itself
+fn parse_block(s: &str) -> Result<BlockId, String> {
+    let (file, n) = s
+        .rsplit_once(':')
+        .ok_or(format!("{} is not of the form <file>:<block>", s))?;
+    Ok(BlockId::new(file, parse_number(n)?))
+}
+
+fn parse_key(s: &str) -> Result<EncryptionKey, String> {
+    let invalid = || "the key must be 64 hex digits".to_string();
+    if s.len() != 64 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(EncryptionKey::new(key))
+}
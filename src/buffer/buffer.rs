@@ -2,23 +2,20 @@ use crate::{
     file::{block_id::BlockId, file_manager::FileManager, page::Page},
     log::log_manager::LogManager,
 };
-use std::{
-    io::Error,
-    sync::{Arc, Mutex},
-};
+use std::{io::Error, sync::Arc};
 
 pub struct Buffer {
     file_manager: Arc<FileManager>,
-    log_manager: Arc<Mutex<LogManager>>,
+    log_manager: Arc<LogManager>,
     contents: Page,
     block_id: Option<BlockId>,
     pins: u32,
     tx_num: Option<u32>,
-    lsn: Option<u32>,
+    lsn: Option<u64>,
 }
 
 impl Buffer {
-    pub fn new(file_manager: Arc<FileManager>, log_manager: Arc<Mutex<LogManager>>) -> Self {
+    pub fn new(file_manager: Arc<FileManager>, log_manager: Arc<LogManager>) -> Self {
         let fm = file_manager.clone();
         let lm = log_manager.clone();
         let contents = Page::new(fm.block_size());
@@ -43,10 +40,16 @@ impl Buffer {
         self.pins > 0
     }
 
-    pub fn set_modified(&mut self, txnum: u32, lsn: u32) {
+    /**
+     * marks the buffer as modified by the transaction. a non-zero lsn is that of the log record
+     * describing the change; it is kept in the page so that the block on disk tells which
+     * records it reflects.
+     */
+    pub fn set_modified(&mut self, txnum: u32, lsn: u64) {
         self.tx_num = Some(txnum);
         if lsn > 0 {
-            self.lsn = Some(lsn);
+            self.lsn = self.lsn.max(Some(lsn));
+            self.contents.set_lsn(self.contents.lsn().max(lsn));
         }
     }
 
@@ -71,13 +74,21 @@ impl Buffer {
         Ok(())
     }
 
+    // forgets the block without writing it back, used when the block is freed
+    pub fn invalidate(&mut self) {
+        self.block_id = None;
+        self.tx_num = None;
+        self.lsn = None;
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
         if self.tx_num.is_some() {
             if let Some(lsn) = self.lsn {
-                self.log_manager.lock().unwrap().flush(lsn)?;
+                self.log_manager.flush(lsn)?;
             }
             if let Some(blk) = &self.block_id {
                 self.file_manager.write(blk, &mut self.contents)?;
+                self.file_manager.sync(blk.filename())?;
             }
             self.tx_num = None;
         }
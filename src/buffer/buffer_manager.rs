@@ -4,7 +4,7 @@ use crate::{
 };
 use std::{
     io::Error,
-    sync::{Arc, Mutex},
+    sync::Arc,
     thread::{current, park_timeout},
     time::SystemTimeError,
 };
@@ -15,6 +15,8 @@ use super::buffer::Buffer;
 pub enum BufferAbortError {
     Time(SystemTimeError),
     IO(Error),
+    // the block cannot be freed while a client has it pinned
+    Pinned(BlockId),
     General,
 }
 
@@ -31,6 +33,7 @@ impl From<Error> for BufferAbortError {
 }
 
 pub struct BufferManager {
+    file_manager: Arc<FileManager>,
     buffer_pool: Vec<Buffer>,
     num_available: u16,
     max_time: u128,
@@ -40,7 +43,7 @@ impl BufferManager {
     const MAX_TIME: u128 = 10 * 1000; // 10 seconds
     pub fn new(
         file_manager: Arc<FileManager>,
-        log_manager: Arc<Mutex<LogManager>>,
+        log_manager: Arc<LogManager>,
         num_buffers: u16,
     ) -> Self {
         let mut buffer_pool = Vec::with_capacity(num_buffers as usize);
@@ -48,6 +51,7 @@ impl BufferManager {
             buffer_pool.push(Buffer::new(file_manager.clone(), log_manager.clone()));
         }
         Self {
+            file_manager,
             buffer_pool,
             num_available: num_buffers,
             max_time: Self::MAX_TIME,
@@ -139,6 +143,43 @@ impl BufferManager {
         &mut self.buffer_pool[idx]
     }
 
+    /**
+     * frees the block in the file manager, dropping the buffer that holds it without writing it back.
+     * fails if the block is pinned. if the file manager cannot free the block, the buffer is kept.
+     */
+    pub fn free_block(&mut self, blk: &BlockId) -> Result<(), BufferAbortError> {
+        let existing = self.find_existing_buffer(blk);
+        if existing.is_some_and(|idx| self.buffer_pool[idx].is_pinned()) {
+            return Err(BufferAbortError::Pinned(blk.clone()));
+        }
+        self.file_manager.free_block(blk)?;
+        if let Some(idx) = existing {
+            self.buffer_pool[idx].invalidate();
+        }
+        Ok(())
+    }
+
+    /**
+     * deletes the file, dropping every buffer that holds one of its blocks.
+     * fails if any of them is pinned. if the file manager cannot delete the file, the buffers are kept.
+     */
+    pub fn remove_file(&mut self, filename: &str) -> Result<(), BufferAbortError> {
+        for buffer in self.buffer_pool.iter() {
+            if let Some(blk) = buffer.block() {
+                if blk.filename() == filename && buffer.is_pinned() {
+                    return Err(BufferAbortError::Pinned(blk.clone()));
+                }
+            }
+        }
+        self.file_manager.remove_file(filename)?;
+        for buffer in self.buffer_pool.iter_mut() {
+            if buffer.block().is_some_and(|blk| blk.filename() == filename) {
+                buffer.invalidate();
+            }
+        }
+        Ok(())
+    }
+
     pub fn set_max_time(&mut self, max_time_m_sec: u128) {
         self.max_time = max_time_m_sec;
     }
@@ -2,12 +2,14 @@
 mod tests {
 
     mod buffer_test {
-        use crate::{app::simple_db::SimpleDB, file::block_id::BlockId};
-        use std::fs;
+        use crate::{
+            app::simple_db::SimpleDB,
+            file::{block_id::BlockId, page::Page},
+        };
 
         #[test]
         fn test_buffer() {
-            let db = SimpleDB::new("test_buffer", 400, 3).unwrap();
+            let db = SimpleDB::new_in_memory(400, 3).unwrap();
             let binding = db.buffer_manager();
             let mut bm = binding.lock().unwrap();
 
@@ -36,18 +38,38 @@ mod tests {
             p2.set_int(80, 9999);
             buffer_2.set_modified(1, 0);
             bm.unpin(idx_2);
+        }
 
-            fs::remove_dir_all("test_buffer").unwrap();
+        #[test]
+        fn test_buffer_lsn() {
+            let db = SimpleDB::new_in_memory(400, 3).unwrap();
+            let lsn = db.log_manager().append(&b"change".to_vec()).unwrap();
+            let binding = db.buffer_manager();
+            let mut bm = binding.lock().unwrap();
+            let blk = BlockId::new("test_buffer_lsn", 0);
+            let idx = bm.pin(&blk).unwrap();
+            let buffer = bm.get_buffer(idx);
+            buffer.contents().set_int(0, 1);
+            buffer.set_modified(1, lsn);
+            // an older lsn does not move the page back
+            buffer.set_modified(1, lsn - 1);
+            buffer.flush().unwrap();
+            bm.unpin(idx);
+
+            // the log record reached the disk before the page that carries its lsn
+            assert_eq!(db.log_manager().get_last_saved_lsn(), lsn);
+            let mut page = Page::new(400);
+            db.file_manager().read(&blk, &mut page).unwrap();
+            assert_eq!(page.lsn(), lsn);
         }
     }
 
     mod buffer_manager_test {
         use crate::{app::simple_db::SimpleDB, file::block_id::BlockId};
-        use std::fs;
 
         #[test]
         fn test_buffer_manager() {
-            let db = SimpleDB::new("test_buffer_manager", 400, 3).unwrap();
+            let db = SimpleDB::new_in_memory(400, 3).unwrap();
             let binding = db.buffer_manager();
             let mut bm = binding.lock().unwrap();
             let mut buffs_idx = Vec::with_capacity(6);
@@ -85,8 +107,134 @@ mod tests {
                 let buffer = bm.get_buffer(buffs_idx[*idx]);
                 assert_eq!(buffer.block().unwrap(), block_id);
             }
+        }
+    }
+
+    mod buffer_free_test {
+        use std::sync::Arc;
+
+        use crate::{
+            app::simple_db::SimpleDB,
+            buffer::buffer_manager::BufferAbortError,
+            file::{
+                block_id::BlockId,
+                storage::{faulty_backend::FaultyBackend, memory_backend::MemoryBackend},
+            },
+        };
+
+        #[test]
+        fn test_free_block() {
+            let db = SimpleDB::new_in_memory(400, 3).unwrap();
+            let fm = db.file_manager();
+            let binding = db.buffer_manager();
+            let mut bm = binding.lock().unwrap();
+            for _ in 0..3 {
+                fm.append("testfile").unwrap();
+            }
+
+            // a block the file manager refuses to free keeps its buffer
+            let past_end = BlockId::new("testfile", 7);
+            let past_end_idx = bm.pin(&past_end).unwrap();
+            bm.unpin(past_end_idx);
+            assert!(bm.free_block(&past_end).is_err());
+            assert_eq!(bm.get_buffer(past_end_idx).block(), Some(&past_end));
+
+            let blk = BlockId::new("testfile", 1);
+            let idx = bm.pin(&blk).unwrap();
+            bm.get_buffer(idx).contents().set_int(0, 5);
+            bm.get_buffer(idx).set_modified(1, 0);
+            assert!(matches!(
+                bm.free_block(&blk),
+                Err(BufferAbortError::Pinned(_))
+            ));
+            bm.unpin(idx);
+
+            // the modification is dropped together with the block
+            bm.free_block(&blk).unwrap();
+            assert!(bm.get_buffer(idx).block().is_none());
+            assert_eq!(fm.append("testfile").unwrap(), blk);
+            let idx = bm.pin(&blk).unwrap();
+            assert_eq!(bm.get_buffer(idx).contents().get_int(0), 0);
+            bm.unpin(idx);
+
+            let idx = bm.pin(&BlockId::new("testfile", 2)).unwrap();
+            assert!(matches!(
+                bm.remove_file("testfile"),
+                Err(BufferAbortError::Pinned(_))
+            ));
+            bm.unpin(idx);
+            bm.remove_file("testfile").unwrap();
+            assert!(bm.get_buffer(idx).block().is_none());
+            assert_eq!(fm.length("testfile").unwrap(), 0);
+        }
+
+        #[test]
+        fn test_remove_file_failure() {
+            let faulty = Arc::new(FaultyBackend::new(Arc::new(MemoryBackend::new())));
+            let db = SimpleDB::with_backend(faulty.clone(), 400, 3).unwrap();
+            let fm = db.file_manager();
+            let binding = db.buffer_manager();
+            let mut bm = binding.lock().unwrap();
+            let blk = fm.append("testfile").unwrap();
+            let idx = bm.pin(&blk).unwrap();
+            bm.get_buffer(idx).contents().set_int(0, 5);
+            bm.get_buffer(idx).set_modified(1, 0);
+            bm.unpin(idx);
+
+            // the file stays, and so does the modified buffer
+            faulty.fail_next_delete();
+            assert!(matches!(
+                bm.remove_file("testfile"),
+                Err(BufferAbortError::IO(_))
+            ));
+            assert_eq!(fm.length("testfile").unwrap(), 1);
+            assert_eq!(bm.get_buffer(idx).block(), Some(&blk));
+            assert_eq!(bm.get_buffer(idx).modifing_tx_num(), Some(1));
+            assert_eq!(bm.get_buffer(idx).contents().get_int(0), 5);
+
+            bm.remove_file("testfile").unwrap();
+            assert!(bm.get_buffer(idx).block().is_none());
+        }
+    }
+
+    mod buffer_fault_test {
+        use std::sync::Arc;
+
+        use crate::{
+            app::simple_db::SimpleDB,
+            buffer::buffer_manager::BufferAbortError,
+            file::{
+                block_id::BlockId,
+                page::Page,
+                storage::{faulty_backend::FaultyBackend, memory_backend::MemoryBackend},
+            },
+        };
+
+        #[test]
+        fn test_buffer_flush_failure() {
+            let faulty = Arc::new(FaultyBackend::new(Arc::new(MemoryBackend::new())));
+            let db = SimpleDB::with_backend(faulty.clone(), 400, 1).unwrap();
+            let binding = db.buffer_manager();
+            let mut bm = binding.lock().unwrap();
 
-            fs::remove_dir_all("test_buffer_manager").unwrap();
+            let blk = BlockId::new("testfile", 0);
+            let idx = bm.pin(&blk).unwrap();
+            bm.get_buffer(idx).contents().set_int(80, 42);
+            bm.get_buffer(idx).set_modified(1, 0);
+            bm.unpin(idx);
+
+            // replacing the buffer has to flush it first, which fails
+            faulty.fail_nth_write(1);
+            let err = bm.pin(&BlockId::new("testfile", 1)).unwrap_err();
+            assert!(matches!(err, BufferAbortError::IO(_)));
+            assert_eq!(bm.get_buffer(idx).block(), Some(&blk));
+            assert_eq!(bm.get_buffer(idx).modifing_tx_num(), Some(1));
+
+            // the modification is written once the storage recovers
+            bm.pin(&BlockId::new("testfile", 1)).unwrap();
+            let mut page = Page::new(400);
+            db.file_manager().read(&blk, &mut page).unwrap();
+            assert_eq!(page.get_int(80), 42);
         }
     }
 }
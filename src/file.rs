@@ -1,4 +1,15 @@
+pub mod block_header;
 pub mod block_id;
+pub mod block_map;
+pub mod checksum;
+pub mod compression;
+pub mod encryption;
 pub mod file_manager;
+pub mod free_list;
+pub mod options;
 pub mod page;
+pub mod stats;
+pub mod storage;
+pub mod superblock;
+pub mod tablespace;
 pub mod test;
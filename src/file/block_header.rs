@@ -0,0 +1,161 @@
+use std::{error, fmt, io, mem::size_of};
+
+use crate::file::{block_id::BlockId, checksum::crc32};
+
+// Every block on disk starts with a fixed size header followed by the page contents.
+// | checksum (u32) | flags (u32) | page lsn (u64) | page contents (block_size) |
+// The checksum covers everything after itself, so a torn or bit-flipped block is detected on read.
+// In an encrypted database the header goes on with the nonce the contents were encrypted under:
+// | checksum (u32) | flags (u32) | page lsn (u64) | nonce (u64) | encrypted page contents |
+pub const HEADER_SIZE: usize = 2 * size_of::<u32>() + size_of::<u64>();
+pub const NONCE_SIZE: usize = size_of::<u64>();
+
+const CHECKSUM_POS: usize = 0;
+const FLAGS_POS: usize = CHECKSUM_POS + size_of::<u32>();
+const LSN_POS: usize = FLAGS_POS + size_of::<u32>();
+
+// set when the page contents are stored compressed, see FileManager::to_physical_block
+pub const FLAG_COMPRESSED: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub flags: u32,
+    pub lsn: u64,
+}
+
+impl BlockHeader {
+    pub fn new(lsn: u64) -> Self {
+        Self { flags: 0, lsn }
+    }
+
+    /**
+     * writes the header and the checksum of the whole block into the first HEADER_SIZE bytes.
+     * @param block the physical block, header followed by the page contents
+     */
+    pub fn stamp(&self, block: &mut [u8]) {
+        block[FLAGS_POS..LSN_POS].copy_from_slice(&self.flags.to_le_bytes());
+        block[LSN_POS..HEADER_SIZE].copy_from_slice(&self.lsn.to_le_bytes());
+        let checksum = crc32(&block[FLAGS_POS..]);
+        block[CHECKSUM_POS..FLAGS_POS].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /**
+     * reads the header of a physical block, verifying its checksum.
+     * a block consisting only of zeros has never been written and is treated as an empty page.
+     */
+    pub fn verify(blk: &BlockId, block: &[u8]) -> Result<Self, CorruptBlockError> {
+        if is_unwritten(block) {
+            return Ok(Self::new(0));
+        }
+        let stored = read_u32(block, CHECKSUM_POS);
+        let computed = crc32(&block[FLAGS_POS..]);
+        if stored != computed {
+            return Err(CorruptBlockError::ChecksumMismatch {
+                block: blk.clone(),
+                stored,
+                computed,
+            });
+        }
+        Ok(Self::read_unverified(block))
+    }
+
+    // reads the header without checking the checksum, for salvaging what is left of a damaged block
+    pub fn read_unverified(block: &[u8]) -> Self {
+        Self {
+            flags: read_u32(block, FLAGS_POS),
+            lsn: u64::from_le_bytes(block[LSN_POS..HEADER_SIZE].try_into().unwrap()),
+        }
+    }
+}
+
+// a block of zeros was never written, e.g. a hole left by writing past the end of a file
+pub fn is_unwritten(block: &[u8]) -> bool {
+    block.iter().all(|b| *b == 0)
+}
+
+fn read_u32(block: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(block[pos..pos + size_of::<u32>()].try_into().unwrap())
+}
+
+#[derive(Debug)]
+pub enum CorruptBlockError {
+    ChecksumMismatch {
+        block: BlockId,
+        stored: u32,
+        computed: u32,
+    },
+    TornWrite {
+        block: BlockId,
+        read_len: usize,
+        expected_len: usize,
+    },
+}
+
+impl CorruptBlockError {
+    pub fn block(&self) -> &BlockId {
+        match self {
+            CorruptBlockError::ChecksumMismatch { block, .. } => block,
+            CorruptBlockError::TornWrite { block, .. } => block,
+        }
+    }
+}
+
+impl fmt::Display for CorruptBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CorruptBlockError::ChecksumMismatch {
+                block,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "corrupt block {}: checksum {:#010x} does not match contents {:#010x}",
+                block, stored, computed
+            ),
+            CorruptBlockError::TornWrite {
+                block,
+                read_len,
+                expected_len,
+            } => write!(
+                f,
+                "torn write in block {}: read {} of {} bytes",
+                block, read_len, expected_len
+            ),
+        }
+    }
+}
+
+impl error::Error for CorruptBlockError {}
+
+// FileManager reports corruption through io::Error so that callers keep a single error type;
+// the typed error can be recovered with `get_ref().downcast_ref::<CorruptBlockError>()`.
+impl From<CorruptBlockError> for io::Error {
+    fn from(err: CorruptBlockError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp_and_verify() {
+        let blk = BlockId::new("test", 3);
+        let mut block = vec![0_u8; HEADER_SIZE + 64];
+        assert_eq!(
+            BlockHeader::verify(&blk, &block).unwrap(),
+            BlockHeader::new(0)
+        );
+
+        block[HEADER_SIZE + 10] = 42;
+        BlockHeader::new(77).stamp(&mut block);
+        assert_eq!(BlockHeader::verify(&blk, &block).unwrap().lsn, 77);
+
+        block[HEADER_SIZE + 11] ^= 1;
+        match BlockHeader::verify(&blk, &block) {
+            Err(CorruptBlockError::ChecksumMismatch { block, .. }) => assert_eq!(block, blk),
+            _ => panic!("expected a checksum mismatch"),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::{io::Error, mem::size_of};
+
+use crate::file::storage::StorageBackend;
+
+// In a compressed database block images differ in size, so each file has a map next to it
+// in a file with this suffix, translating block numbers into the place of the image in the file.
+// | offset (u64) | len (u32) | capacity (u32) | per block, little endian
+pub const BLOCK_MAP_SUFFIX: &str = ".map";
+const ENTRY_SIZE: usize = size_of::<u64>() + 2 * size_of::<u32>();
+// slots are rounded up to this size so that an image can grow a little and still be rewritten in place
+const SLOT_ALIGN: usize = 64;
+
+pub fn block_map_file(filename: &str) -> String {
+    format!("{}{}", filename, BLOCK_MAP_SUFFIX)
+}
+
+// Where the image of a block is stored. A len of 0 means the block was never written
+// and reads as an empty page.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MapEntry {
+    pub offset: u64,
+    pub len: u32,
+    pub capacity: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct BlockMap {
+    entries: Vec<MapEntry>,
+    // end of the data file, where new slots are allocated
+    end: u64,
+    // slots no block is mapped to, as (offset, capacity), reused before the file grows
+    free: Vec<(u64, u64)>,
+    // slots let go since the map was last synced. the map on disk may still point at them,
+    // so they are kept as they are until then, see reuse_released
+    released: Vec<(u64, u64)>,
+}
+
+impl BlockMap {
+    pub fn load(storage: &dyn StorageBackend, filename: &str) -> Result<Self, Error> {
+        let map_file = block_map_file(filename);
+        let mut b = vec![0; storage.length(&map_file)? as usize];
+        if !b.is_empty() {
+            storage.read_at(&map_file, 0, &mut b)?;
+        }
+        let entries: Vec<MapEntry> = b
+            .chunks_exact(ENTRY_SIZE)
+            .map(|e| MapEntry {
+                offset: u64::from_le_bytes(e[0..8].try_into().unwrap()),
+                len: u32::from_le_bytes(e[8..12].try_into().unwrap()),
+                capacity: u32::from_le_bytes(e[12..16].try_into().unwrap()),
+            })
+            .collect();
+        let end = entries
+            .iter()
+            .map(|e| e.offset + e.capacity as u64)
+            .fold(storage.length(filename)?, u64::max);
+        // whatever lies between the mapped slots is free
+        let mut used: Vec<(u64, u64)> = entries
+            .iter()
+            .filter(|e| e.capacity > 0)
+            .map(|e| (e.offset, e.capacity as u64))
+            .collect();
+        used.sort_unstable();
+        let mut free = Vec::new();
+        let mut pos = 0;
+        for (offset, capacity) in used.into_iter().chain([(end, 0)]) {
+            if offset > pos {
+                free.push((pos, offset - pos));
+            }
+            pos = pos.max(offset + capacity);
+        }
+        Ok(Self {
+            entries,
+            end,
+            free,
+            released: Vec::new(),
+        })
+    }
+
+    // number of blocks in the file
+    pub fn len(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, blk_num: u64) -> MapEntry {
+        self.entries
+            .get(blk_num as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /**
+     * reserves a slot for an image of len bytes, taking the smallest free slot it fits into
+     * or else growing the data file.
+     */
+    pub fn allocate(&mut self, len: usize) -> MapEntry {
+        let capacity = (len.div_ceil(SLOT_ALIGN) * SLOT_ALIGN) as u64;
+        let best = (0..self.free.len())
+            .filter(|i| self.free[*i].1 >= capacity)
+            .min_by_key(|i| self.free[*i].1);
+        let offset = match best {
+            Some(i) => {
+                let (offset, free_capacity) = self.free[i];
+                if free_capacity == capacity {
+                    self.free.swap_remove(i);
+                } else {
+                    self.free[i] = (offset + capacity, free_capacity - capacity);
+                }
+                offset
+            }
+            None => {
+                self.end += capacity;
+                self.end - capacity
+            }
+        };
+        MapEntry {
+            offset,
+            len: len as u32,
+            capacity: capacity as u32,
+        }
+    }
+
+    fn release(&mut self, entry: MapEntry) {
+        if entry.capacity > 0 {
+            self.released.push((entry.offset, entry.capacity as u64));
+        }
+    }
+
+    // makes the slots let go so far free for reuse. only call this once the map is synced.
+    pub fn reuse_released(&mut self) {
+        self.free.append(&mut self.released);
+        // neighbouring slots are merged so that bigger images fit into them
+        self.free.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.free.len());
+        for (offset, capacity) in self.free.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.0 + last.1 == offset => last.1 += capacity,
+                _ => merged.push((offset, capacity)),
+            }
+        }
+        self.free = merged;
+    }
+
+    /**
+     * records where block blk_num is stored. blocks between the end of the file and blk_num
+     * are added as never written. the slot the block had before is released.
+     */
+    pub fn set(
+        &mut self,
+        storage: &dyn StorageBackend,
+        filename: &str,
+        blk_num: u64,
+        entry: MapEntry,
+    ) -> Result<(), Error> {
+        let first = (blk_num as usize).min(self.entries.len());
+        if blk_num as usize >= self.entries.len() {
+            self.entries
+                .resize(blk_num as usize + 1, MapEntry::default());
+        }
+        let old = std::mem::replace(&mut self.entries[blk_num as usize], entry);
+        if old.offset != entry.offset {
+            self.release(old);
+        }
+        let b: Vec<u8> = self.entries[first..=blk_num as usize]
+            .iter()
+            .flat_map(|e| {
+                let mut b = e.offset.to_le_bytes().to_vec();
+                b.extend_from_slice(&e.len.to_le_bytes());
+                b.extend_from_slice(&e.capacity.to_le_bytes());
+                b
+            })
+            .collect();
+        storage.write_at(&block_map_file(filename), (first * ENTRY_SIZE) as u64, &b)
+    }
+
+    /**
+     * drops the blocks from len on and releases their slots. the data file keeps its size.
+     */
+    pub fn truncate(
+        &mut self,
+        storage: &dyn StorageBackend,
+        filename: &str,
+        len: u64,
+    ) -> Result<(), Error> {
+        for entry in self
+            .entries
+            .split_off((len as usize).min(self.entries.len()))
+        {
+            self.release(entry);
+        }
+        storage.truncate(&block_map_file(filename), len * ENTRY_SIZE as u64)
+    }
+}
@@ -0,0 +1,156 @@
+// A small LZ77 codec in the style of LZ4, used to compress block images.
+// The compressed form is a series of sequences, each a run of literal bytes followed by a
+// match copying earlier output:
+// | token (u8) | extra literal length | literals | match offset (u16) | extra match length |
+// The high nibble of the token holds the literal length and the low nibble the match length
+// minus MIN_MATCH; a nibble of 15 is continued by bytes of 255 ending with a smaller one.
+// The last sequence has literals only.
+const MIN_MATCH: usize = 4;
+const HASH_BITS: u32 = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    // position of the last occurrence of each hashed 4 byte sequence
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..i + MIN_MATCH]);
+        let candidate = table[h];
+        table[h] = i;
+        if candidate != usize::MAX
+            && i - candidate <= MAX_OFFSET
+            && input[candidate..candidate + MIN_MATCH] == input[i..i + MIN_MATCH]
+        {
+            let mut match_len = MIN_MATCH;
+            while i + match_len < input.len()
+                && input[candidate + match_len] == input[i + match_len]
+            {
+                match_len += 1;
+            }
+            emit_sequence(
+                &mut out,
+                &input[anchor..i],
+                Some((i - candidate, match_len)),
+            );
+            i += match_len;
+            anchor = i;
+        } else {
+            i += 1;
+        }
+    }
+    emit_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+/**
+ * decompresses input, which has to expand to at most max_len bytes.
+ * returns None if input is not a valid compressed image.
+ */
+pub fn decompress(input: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(max_len);
+    let mut pos = 0;
+    loop {
+        let token = *input.get(pos)?;
+        pos += 1;
+        let literal_len = read_len(input, &mut pos, (token >> 4) as usize)?;
+        let literals = input.get(pos..pos + literal_len)?;
+        if out.len() + literal_len > max_len {
+            return None;
+        }
+        out.extend_from_slice(literals);
+        pos += literal_len;
+        if pos == input.len() {
+            return Some(out);
+        }
+        let offset = u16::from_le_bytes(input.get(pos..pos + 2)?.try_into().unwrap()) as usize;
+        pos += 2;
+        let match_len = read_len(input, &mut pos, (token & 0x0f) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > out.len() || out.len() + match_len > max_len {
+            return None;
+        }
+        // the match may overlap the bytes it produces, so it is copied byte by byte
+        let start = out.len() - offset;
+        for j in 0..match_len {
+            out.push(out[start + j]);
+        }
+    }
+}
+
+fn hash(b: &[u8]) -> usize {
+    let v = u32::from_le_bytes(b.try_into().unwrap());
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn emit_sequence(out: &mut Vec<u8>, literals: &[u8], m: Option<(usize, usize)>) {
+    let match_len = m.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    write_len(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = m {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_len(out, match_len);
+    }
+}
+
+// writes the part of a length that did not fit into its nibble
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    if len < 15 {
+        return;
+    }
+    let mut rest = len - 15;
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    out.push(rest as u8);
+}
+
+fn read_len(input: &[u8], pos: &mut usize, nibble: usize) -> Option<usize> {
+    let mut len = nibble;
+    if nibble == 15 {
+        loop {
+            let b = *input.get(*pos)?;
+            *pos += 1;
+            len += b as usize;
+            if b != 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_decompress() {
+        let mut repetitive = Vec::new();
+        for i in 0..100 {
+            repetitive.extend_from_slice(format!("customer {} lives in Tokyo; ", i % 3).as_bytes());
+        }
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"abc".to_vec(),
+            vec![0; 4096],
+            (0..=255).collect(),
+            (0..5000u32).map(|i| (i * 7919 % 251) as u8).collect(),
+            repetitive.clone(),
+        ];
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+        assert!(compress(&repetitive).len() < repetitive.len() / 10);
+        assert!(compress(&[0; 4096]).len() < 64);
+
+        // output that would exceed max_len and malformed input are rejected
+        let compressed = compress(&repetitive);
+        assert!(decompress(&compressed, repetitive.len() - 1).is_none());
+        assert!(decompress(&compressed[..compressed.len() / 2], repetitive.len()).is_none());
+        assert!(decompress(&[0x0f, 0, 0], 100).is_none());
+    }
+}
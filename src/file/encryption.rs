@@ -0,0 +1,133 @@
+use std::fmt;
+
+use crate::file::checksum::crc32;
+
+// The 256 bit key a database is encrypted with. FileManager encrypts the contents of every
+// block with ChaCha20 before the checksum is computed, so corruption is still caught on read
+// without the key, and the block header stays readable.
+//
+// Every write of a block is encrypted under a nonce of its own, which FileManager hands out
+// from a counter and stores in the plaintext block header so that the block can be decrypted.
+// No two writes share a keystream, so the versions of a block someone may have kept tell
+// nothing about each other. Blocks known to be empty are stored as zeros without encryption,
+// as encrypting them would put the keystream itself on disk.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+// plaintext of the key check stored in the superblock
+const KEY_CHECK: &[u8; KEY_CHECK_SIZE] = b"simpledb key chk";
+pub const KEY_CHECK_SIZE: usize = 16;
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    // encrypts or decrypts a block written under the nonce, the two are the same operation
+    pub fn apply(&self, nonce: u64, data: &mut [u8]) {
+        let mut block_nonce = [0; 12];
+        block_nonce[4..].copy_from_slice(&nonce.to_le_bytes());
+        chacha20(&self.0, &block_nonce, 0, data);
+    }
+
+    /**
+     * returns a value that only this key produces, stored when the database is created
+     * so that opening it with another key fails before any block is read.
+     */
+    pub fn key_check(&self, filename: &str) -> [u8; KEY_CHECK_SIZE] {
+        let mut check = *KEY_CHECK;
+        chacha20(&self.0, &key_check_nonce(filename), 0, &mut check);
+        check
+    }
+}
+
+// keeps the key out of logs and panic messages
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+// never one of a block, whose first four bytes are zero
+fn key_check_nonce(filename: &str) -> [u8; 12] {
+    let mut nonce = [0xff; 12];
+    nonce[..4].copy_from_slice(&crc32(filename.as_bytes()).to_le_bytes());
+    nonce
+}
+
+/**
+ * xors data with the ChaCha20 keystream (RFC 8439) starting at the given block counter.
+ */
+pub fn chacha20(key: &[u8; 32], nonce: &[u8; 12], counter: u32, data: &mut [u8]) {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (i, word) in key.chunks_exact(4).enumerate() {
+        state[4 + i] = u32::from_le_bytes(word.try_into().unwrap());
+    }
+    for (i, word) in nonce.chunks_exact(4).enumerate() {
+        state[13 + i] = u32::from_le_bytes(word.try_into().unwrap());
+    }
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        state[12] = counter.wrapping_add(i as u32);
+        let keystream = chacha20_block(&state);
+        for (b, k) in chunk.iter_mut().zip(keystream) {
+            *b ^= k;
+        }
+    }
+}
+
+fn chacha20_block(input: &[u32; 16]) -> [u8; 64] {
+    let mut x = *input;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    let mut out = [0; 64];
+    for i in 0..16 {
+        let word = x[i].wrapping_add(input[i]);
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chacha20() {
+        // test vector from RFC 8439 section 2.4.2
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let mut data = plaintext.to_vec();
+        chacha20(&key, &nonce, 1, &mut data);
+        assert_eq!(
+            data[..16],
+            [
+                0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d,
+                0x69, 0x81
+            ]
+        );
+        assert_eq!(data[data.len() - 2..], [0x87, 0x4d]);
+        chacha20(&key, &nonce, 1, &mut data);
+        assert_eq!(data, plaintext);
+    }
+}
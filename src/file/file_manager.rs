@@ -1,117 +1,1088 @@
-use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{Error, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use std::{fs, path};
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 
+use crate::file::block_header::{
+    is_unwritten, BlockHeader, CorruptBlockError, FLAG_COMPRESSED, HEADER_SIZE, NONCE_SIZE,
+};
 use crate::file::block_id::BlockId;
+use crate::file::block_map::{block_map_file, BlockMap, MapEntry, BLOCK_MAP_SUFFIX};
+use crate::file::compression::{compress, decompress};
+use crate::file::encryption::EncryptionKey;
+use crate::file::free_list::{free_list_file, FreeList, FREE_LIST_SUFFIX};
+use crate::file::options::{Durability, FileOptions};
 use crate::file::page::Page;
+use crate::file::stats::{FileCounters, FileKind, IoStats};
+use crate::file::storage::{fs_backend::FsBackend, StorageBackend, SyncMode};
+use crate::file::superblock::{Superblock, CONTROL_FILE};
+use crate::file::tablespace::{TablespaceCatalog, DEFAULT_TABLESPACE};
+
+// files whose name starts with this prefix hold temporary tables and are deleted on startup.
+// the prefix is reserved for the names handed out by FileManager::temp_file.
+pub const TEMP_PREFIX: &str = ".temp";
+// files are moved between tablespaces in pieces of this many bytes
+const COPY_CHUNK: usize = 1 << 20;
+// nonces are reserved in the superblock this many at a time
+const NONCE_BATCH: u64 = 1 << 16;
 
 pub struct FileManager {
-    db_directory: path::PathBuf,
+    // storage of the default tablespace, which also holds the control file and the tablespace catalog
+    storage: Arc<dyn StorageBackend>,
+    tablespaces: RwLock<Tablespaces>,
     block_size: usize,
     is_new: bool,
-    open_files: Mutex<HashMap<PathBuf, Arc<Mutex<fs::File>>>>,
+    superblock: Superblock,
+    // free lists of the files touched so far, loaded on first use
+    free_lists: Mutex<HashMap<String, FreeList>>,
+    // block maps of the files touched so far, only used when the database is compressed
+    block_maps: Mutex<HashMap<String, BlockMap>>,
+    durability: Durability,
+    encryption_key: Option<EncryptionKey>,
+    // nonces left to encrypt blocks with, see next_nonce
+    nonces: Mutex<NonceRange>,
+    // files whose directory entry has already been synced
+    synced_files: Mutex<HashSet<String>>,
+    data_syncs: AtomicU64,
+    full_syncs: AtomicU64,
+    directory_syncs: AtomicU64,
+    block_bytes_written: AtomicU64,
+    stored_bytes_written: AtomicU64,
+    // I/O counters of every file used since the last reset
+    counters: RwLock<HashMap<String, Arc<FileCounters>>>,
+    // files that are not data files, see set_file_kind
+    file_kinds: Mutex<HashMap<String, FileKind>>,
+    // number of the next temp file, see temp_file
+    next_temp: AtomicU64,
+}
+
+// The tablespaces of the database with their storage, and which files live where.
+struct Tablespaces {
+    catalog: TablespaceCatalog,
+    storages: HashMap<String, Arc<dyn StorageBackend>>,
+    // where temp files go unless they were placed elsewhere
+    temp: String,
+}
+
+impl Tablespaces {
+    fn name_of(&self, filename: &str) -> &str {
+        let filename = owner_file(filename);
+        match self.catalog.tablespace_of(filename) {
+            Some(name) => name,
+            None if is_temp_file(filename) => &self.temp,
+            None => DEFAULT_TABLESPACE,
+        }
+    }
+
+    /**
+     * fails if the storage at the location is that of the database or of one of its tablespaces.
+     * files moved or archived there would be copied onto themselves and lost.
+     */
+    fn check_separate(&self, storage: &dyn StorageBackend, location: &str) -> Result<(), Error> {
+        let identity = storage.identity()?;
+        for (name, other) in &self.storages {
+            if other.identity()? == identity {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} is where tablespace {} is", location, name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn storage(&self, name: &str) -> Result<Arc<dyn StorageBackend>, Error> {
+        self.storages.get(name).cloned().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown tablespace {}", name),
+            )
+        })
+    }
+}
+
+// The nonces [next, limit) are reserved in the superblock and not used yet.
+struct NonceRange {
+    next: u64,
+    limit: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStats {
+    pub data_syncs: u64,
+    pub full_syncs: u64,
+    pub directory_syncs: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+    // bytes of block images handed to FileManager for writing
+    pub block_bytes: u64,
+    // bytes that were actually written for them
+    pub stored_bytes: u64,
+}
+
+impl CompressionStats {
+    // how many times smaller the written blocks were on disk, 1.0 when nothing was written
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 1.0;
+        }
+        self.block_bytes as f64 / self.stored_bytes as f64
+    }
 }
 
 impl FileManager {
     pub fn new(db_directory: path::PathBuf, block_size: usize) -> Result<Self, Error> {
-        let is_new = !db_directory.exists();
-        if is_new {
-            fs::create_dir_all(&db_directory)?;
-        }
-
-        // remove any leftover temp tables
-        match fs::read_dir(path::Path::new(&db_directory)) {
-            Ok(entries) => {
-                for entry in entries {
-                    match entry {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            if path.is_file() {
-                                match fs::remove_file(path) {
-                                    Ok(_) => {}
-                                    Err(e) => println!("cannot remove file: {}", e),
-                                }
-                            }
+        Self::with_backend(Arc::new(FsBackend::new(db_directory)?), block_size)
+    }
+
+    pub fn with_backend(
+        storage: Arc<dyn StorageBackend>,
+        block_size: usize,
+    ) -> Result<Self, Error> {
+        Self::with_options(storage, block_size, &FileOptions::default())
+    }
+
+    pub fn with_options(
+        storage: Arc<dyn StorageBackend>,
+        block_size: usize,
+        options: &FileOptions,
+    ) -> Result<Self, Error> {
+        let is_new = storage.length(CONTROL_FILE)? == 0;
+        if is_new && options.read_only {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "there is no database to open read-only",
+            ));
+        }
+
+        let mut catalog = TablespaceCatalog::load(storage.as_ref())?;
+        let mut catalog_changed = false;
+        for (name, location) in &options.tablespaces {
+            match catalog.location(name) {
+                Some(l) if l == location => {}
+                Some(l) => {
+                    return Err(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("tablespace {} is already at {}", name, l),
+                    ))
+                }
+                None => {
+                    catalog.add(name, location)?;
+                    catalog_changed = true;
+                }
+            }
+        }
+        let mut tablespaces = Tablespaces {
+            catalog: TablespaceCatalog::default(),
+            storages: HashMap::from([(DEFAULT_TABLESPACE.to_string(), storage.clone())]),
+            temp: options
+                .temp_tablespace
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TABLESPACE.to_string()),
+        };
+        for (name, location) in catalog.tablespaces() {
+            let tablespace = storage.open_tablespace(location)?;
+            tablespaces.check_separate(tablespace.as_ref(), location)?;
+            tablespaces.storages.insert(name.to_string(), tablespace);
+        }
+        tablespaces.catalog = catalog;
+        tablespaces.storage(&tablespaces.temp)?;
+        // log segments archived into the database or a tablespace would be copied onto themselves
+        if let (Some(location), false) = (&options.log_archive, options.read_only) {
+            tablespaces.check_separate(storage.open_tablespace(location)?.as_ref(), location)?;
+        }
+
+        // remove any leftover temp tables, every other file belongs to the database
+        if !options.read_only {
+            for tablespace in tablespaces.storages.values() {
+                for filename in tablespace.files()? {
+                    if is_temp_file(&filename) {
+                        match tablespace.delete(&filename) {
+                            Ok(_) => {}
+                            Err(e) => println!("cannot remove file: {}", e),
                         }
-                        Err(e) => println!("cannot read directory: {}", e),
                     }
                 }
             }
-            Err(e) => panic!("cannot read directory: {}", e),
+        }
+
+        let superblock = Superblock::open(storage.as_ref(), block_size, options)?;
+        let nonce_limit = superblock.nonce_limit();
+
+        catalog_changed |= tablespaces.catalog.remove_files(is_temp_file);
+        if catalog_changed && !options.read_only {
+            tablespaces.catalog.store(storage.as_ref())?;
         }
 
         Ok(Self {
-            db_directory,
+            storage,
+            tablespaces: RwLock::new(tablespaces),
             block_size,
             is_new,
-            open_files: Mutex::new(HashMap::new()),
+            superblock,
+            free_lists: Mutex::new(HashMap::new()),
+            block_maps: Mutex::new(HashMap::new()),
+            durability: options.durability,
+            encryption_key: options.encryption_key.clone(),
+            nonces: Mutex::new(NonceRange {
+                next: nonce_limit,
+                limit: nonce_limit,
+            }),
+            synced_files: Mutex::new(HashSet::new()),
+            data_syncs: AtomicU64::new(0),
+            full_syncs: AtomicU64::new(0),
+            directory_syncs: AtomicU64::new(0),
+            block_bytes_written: AtomicU64::new(0),
+            stored_bytes_written: AtomicU64::new(0),
+            counters: RwLock::new(HashMap::new()),
+            file_kinds: Mutex::new(HashMap::new()),
+            next_temp: AtomicU64::new(0),
         })
     }
 
+    /**
+     * reads a block into the page, verifying its checksum.
+     * blocks past the end of the file read as an empty page.
+     */
     pub fn read(&self, blk: &BlockId, page: &mut Page) -> Result<(), Error> {
-        let file_io = self.get_file(blk.filename())?;
-        let mut file_io = file_io.lock().unwrap();
-        let offset = blk.number() * self.block_size as u64;
-        file_io.seek(SeekFrom::Start(offset))?;
-        let mut tmp_buff = vec![0; self.block_size];
-        let read_len = file_io.read(&mut tmp_buff)?;
-        if read_len == self.block_size {
-            page.byte_buffer = tmp_buff;
+        let start = Instant::now();
+        if self.is_compressed() {
+            let (tmp_buff, read_len) = self.read_mapped(blk)?;
+            self.counters(blk.filename())
+                .record_read(1, read_len, start.elapsed());
+            return self.load_physical_block(blk, &tmp_buff, read_len, page);
         }
-        Ok(())
+        let mut tmp_buff = vec![0; self.physical_block_size()];
+        let read_len = self.storage_for(blk.filename()).read_block(
+            blk.filename(),
+            blk.number(),
+            &mut tmp_buff,
+        )?;
+        self.counters(blk.filename())
+            .record_read(1, read_len, start.elapsed());
+        self.load_physical_block(blk, &tmp_buff, read_len, page)
+    }
+
+    /**
+     * reads a block like read, but a block that fails its checksum is still copied into the page
+     * as far as it can be made sense of, so that what a torn write left of it can be salvaged.
+     * returns whether the block was intact.
+     */
+    pub fn read_salvaged(&self, blk: &BlockId, page: &mut Page) -> Result<bool, Error> {
+        let err = match self.read(blk, page) {
+            Ok(()) => return Ok(true),
+            Err(e) => e,
+        };
+        let is_corrupt = err
+            .get_ref()
+            .is_some_and(|e| e.downcast_ref::<CorruptBlockError>().is_some());
+        if !is_corrupt {
+            return Err(err);
+        }
+        let mut b = if self.is_compressed() {
+            self.read_mapped(blk)?.0
+        } else {
+            let mut b = vec![0; self.physical_block_size()];
+            self.storage_for(blk.filename())
+                .read_block(blk.filename(), blk.number(), &mut b)?;
+            b
+        };
+        b.resize(b.len().max(self.header_size()), 0);
+        let header = BlockHeader::read_unverified(&b);
+        let mut contents = b.split_off(self.header_size());
+        if let Some(key) = &self.encryption_key {
+            key.apply(read_nonce(&b), &mut contents);
+        }
+        if header.flags & FLAG_COMPRESSED != 0 {
+            contents = decompress(&contents, self.block_size).unwrap_or_default();
+        }
+        contents.resize(self.block_size, 0);
+        page.byte_buffer = contents;
+        page.set_lsn(header.lsn);
+        Ok(false)
     }
+
+    /**
+     * writes the page to the block, stamping the header with the page lsn and a checksum.
+     * the page contents are compressed and then encrypted, as far as the database is set up
+     * to, before the checksum is taken. blocks of the log are never compressed, see is_log.
+     */
     pub fn write(&self, blk: &BlockId, page: &mut Page) -> Result<(), Error> {
-        let file_io = self.get_file(blk.filename())?;
-        let mut file_io = file_io.lock().unwrap();
-        let offset = blk.number() * self.block_size as u64;
-        file_io.seek(SeekFrom::Start(offset))?;
-        file_io.write_all(&page.byte_buffer[0..self.block_size()])?;
-        file_io.flush()?;
+        self.write_physical_block(blk, &self.to_physical_block(blk.filename(), page)?)
+    }
+
+    /**
+     * reads blks[i] into pages[i]. runs of consecutive blocks of the same file are read
+     * with a single call to the storage, which suits sequential scans.
+     */
+    pub fn read_many(&self, blks: &[BlockId], pages: &mut [Page]) -> Result<(), Error> {
+        assert_eq!(blks.len(), pages.len(), "one page is needed per block");
+        if self.is_compressed() {
+            // compressed images are scattered over the file, each is read on its own
+            for (blk, page) in blks.iter().zip(pages) {
+                self.read(blk, page)?;
+            }
+            return Ok(());
+        }
+        let physical_block_size = self.physical_block_size();
+        for (start, end) in consecutive_runs(blks) {
+            let mut tmp_buff = vec![0; (end - start) * physical_block_size];
+            let started = Instant::now();
+            let read_len = self.storage_for(blks[start].filename()).read_blocks(
+                blks[start].filename(),
+                blks[start].number(),
+                physical_block_size,
+                &mut tmp_buff,
+            )?;
+            self.counters(blks[start].filename()).record_read(
+                (end - start) as u64,
+                read_len,
+                started.elapsed(),
+            );
+            for (i, block) in tmp_buff.chunks(physical_block_size).enumerate() {
+                let block_read_len = read_len
+                    .saturating_sub(i * physical_block_size)
+                    .min(physical_block_size);
+                self.load_physical_block(
+                    &blks[start + i],
+                    block,
+                    block_read_len,
+                    &mut pages[start + i],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * writes pages[i] to blks[i]. runs of consecutive blocks of the same file are written
+     * with a single call to the storage, which suits bulk flushes.
+     */
+    pub fn write_many(&self, blks: &[BlockId], pages: &[Page]) -> Result<(), Error> {
+        assert_eq!(blks.len(), pages.len(), "one page is needed per block");
+        if self.is_compressed() {
+            for (blk, page) in blks.iter().zip(pages) {
+                self.write_physical_block(blk, &self.to_physical_block(blk.filename(), page)?)?;
+            }
+            return Ok(());
+        }
+        for (start, end) in consecutive_runs(blks) {
+            let mut b = Vec::with_capacity((end - start) * self.physical_block_size());
+            for page in &pages[start..end] {
+                b.extend_from_slice(&self.to_physical_block(blks[start].filename(), page)?);
+            }
+            self.count_written(b.len(), b.len());
+            let started = Instant::now();
+            self.storage_for(blks[start].filename()).write_blocks(
+                blks[start].filename(),
+                blks[start].number(),
+                self.physical_block_size(),
+                &b,
+            )?;
+            self.counters(blks[start].filename()).record_write(
+                (end - start) as u64,
+                b.len(),
+                started.elapsed(),
+            );
+        }
         Ok(())
     }
 
+    /**
+     * adds an empty block to the file and returns it.
+     * blocks on the free list of the file are reused before the file grows.
+     */
     pub fn append(&self, filename: &str) -> Result<BlockId, Error> {
-        let new_blk_num = self.length(filename)?;
-        let blk = BlockId::new(filename, new_blk_num);
-        let file_io = self.get_file(blk.filename())?;
-        let mut file_io = file_io.lock().unwrap();
-        let b = vec![0; self.block_size()];
-        file_io.seek(SeekFrom::Start(blk.number() * self.block_size() as u64))?;
-        file_io.write_all(&b)?;
-        file_io.flush()?;
-        Ok(blk)
+        let start = Instant::now();
+        let mut free_lists = self.free_lists.lock().unwrap();
+        let free_list = self.free_list(&mut free_lists, filename)?;
+        if let Some(blk_num) = free_list.take() {
+            let blk = BlockId::new(filename, blk_num);
+            self.clear_block(&blk)?;
+            free_list.store(self.storage_for(filename).as_ref(), filename)?;
+            self.counters(filename).record_append(0, start.elapsed());
+            return Ok(blk);
+        }
+        if self.is_compressed() {
+            // the new block is mapped as never written, nothing goes to the data file yet
+            let mut block_maps = self.block_maps.lock().unwrap();
+            let block_map = self.block_map(&mut block_maps, filename)?;
+            let new_blk_num = block_map.len();
+            block_map.set(
+                self.storage_for(filename).as_ref(),
+                filename,
+                new_blk_num,
+                MapEntry::default(),
+            )?;
+            self.counters(filename).record_append(0, start.elapsed());
+            return Ok(BlockId::new(filename, new_blk_num));
+        }
+        // a block of zeros reads back as an empty page, so it needs neither a header nor encryption
+        let b = vec![0; self.physical_block_size()];
+        let new_blk_num = self.storage_for(filename).append(filename, &b)?;
+        self.counters(filename)
+            .record_append(b.len(), start.elapsed());
+        Ok(BlockId::new(filename, new_blk_num))
+    }
+
+    /**
+     * puts the block on the free list of its file so that a later append reuses it.
+     * buffers holding the block have to be dropped first, see BufferManager::free_block.
+     */
+    pub fn free_block(&self, blk: &BlockId) -> Result<(), Error> {
+        if blk.number() >= self.length(blk.filename())? {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("cannot free {}, it is past the end of the file", blk),
+            ));
+        }
+        let mut free_lists = self.free_lists.lock().unwrap();
+        let free_list = self.free_list(&mut free_lists, blk.filename())?;
+        if !free_list.insert(blk.number()) {
+            return Ok(());
+        }
+        self.clear_block(blk)?;
+        free_list.store(self.storage_for(blk.filename()).as_ref(), blk.filename())
+    }
+
+    /**
+     * shrinks the file by cutting off the free blocks at its end.
+     * returns the number of blocks removed.
+     */
+    pub fn truncate(&self, filename: &str) -> Result<u64, Error> {
+        let mut free_lists = self.free_lists.lock().unwrap();
+        let free_list = self.free_list(&mut free_lists, filename)?;
+        let length = self.length(filename)?;
+        let mut new_length = length;
+        while new_length > 0 && free_list.remove(new_length - 1) {
+            new_length -= 1;
+        }
+        if new_length < length {
+            self.cut_file(free_list, filename, new_length)?;
+        }
+        Ok(length - new_length)
+    }
+
+    /**
+     * cuts the file down to its first length blocks, whether or not the blocks past them are free.
+     * used to throw away a damaged tail.
+     */
+    pub fn truncate_to(&self, filename: &str, length: u64) -> Result<(), Error> {
+        let mut free_lists = self.free_lists.lock().unwrap();
+        let free_list = self.free_list(&mut free_lists, filename)?;
+        let old_length = self.length(filename)?;
+        if length >= old_length {
+            return Ok(());
+        }
+        for blk_num in length..old_length {
+            free_list.remove(blk_num);
+        }
+        self.cut_file(free_list, filename, length)
+    }
+
+    fn cut_file(&self, free_list: &FreeList, filename: &str, length: u64) -> Result<(), Error> {
+        let storage = self.storage_for(filename);
+        if self.is_compressed() {
+            let mut block_maps = self.block_maps.lock().unwrap();
+            let block_map = self.block_map(&mut block_maps, filename)?;
+            block_map.truncate(storage.as_ref(), filename, length)?;
+            self.release_slots(block_map);
+        } else {
+            storage.truncate(filename, length * self.physical_block_size() as u64)?;
+        }
+        free_list.store(storage.as_ref(), filename)
+    }
+
+    // closes any handle the storage keeps open for the file
+    pub fn close_file(&self, filename: &str) -> Result<(), Error> {
+        self.storage_for(filename).close(filename)
     }
+
+    // removes the file together with its free list and block map
+    pub fn remove_file(&self, filename: &str) -> Result<(), Error> {
+        let mut free_lists = self.free_lists.lock().unwrap();
+        free_lists.remove(filename);
+        self.block_maps.lock().unwrap().remove(filename);
+        let storage = self.storage_for(filename);
+        storage.delete(filename)?;
+        storage.delete(&free_list_file(filename))?;
+        storage.delete(&block_map_file(filename))?;
+        let mut tablespaces = self.tablespaces.write().unwrap();
+        if tablespaces.catalog.tablespace_of(filename).is_some() {
+            tablespaces.catalog.place(filename, DEFAULT_TABLESPACE)?;
+            tablespaces.catalog.store(self.storage.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /**
+     * moves the file, with its free list and block map, out of the database into the location,
+     * which is opened the way a tablespace would be, e.g. to keep old log segments around.
+     * the copy is synced before the file is removed.
+     */
+    pub fn archive_file(&self, filename: &str, location: &str) -> Result<(), Error> {
+        let from = self.storage_for(filename);
+        let to = self.storage.open_tablespace(location)?;
+        for name in [
+            filename.to_string(),
+            free_list_file(filename),
+            block_map_file(filename),
+        ] {
+            copy_file(from.as_ref(), to.as_ref(), &name)?;
+        }
+        to.sync_directory()?;
+        self.remove_file(filename)?;
+        from.sync_directory()
+    }
+
+    /**
+     * copies a file archived to the location back into the database, with its free list and
+     * block map, replacing what the database has under its name. the archived copy is kept.
+     */
+    pub fn restore_file(&self, filename: &str, location: &str) -> Result<(), Error> {
+        let from = self.storage.open_tablespace(location)?;
+        let to = self.storage_for(filename);
+        for name in [
+            filename.to_string(),
+            free_list_file(filename),
+            block_map_file(filename),
+        ] {
+            copy_file(from.as_ref(), to.as_ref(), &name)?;
+        }
+        // a free list or block map read before is stale now
+        self.free_lists.lock().unwrap().remove(filename);
+        self.block_maps.lock().unwrap().remove(filename);
+        to.sync_directory()
+    }
+
+    // names of the files archived to the location
+    pub fn archived_files(&self, location: &str) -> Result<Vec<String>, Error> {
+        self.storage.open_tablespace(location)?.files()
+    }
+
+    // names of the files in the default tablespace
+    pub fn files(&self) -> Result<Vec<String>, Error> {
+        self.storage.files()
+    }
+
+    // number of blocks of the file that are free to be reused
+    pub fn free_blocks(&self, filename: &str) -> Result<usize, Error> {
+        let mut free_lists = self.free_lists.lock().unwrap();
+        Ok(self.free_list(&mut free_lists, filename)?.len())
+    }
+
+    /**
+     * creates a tablespace at the location, which for the filesystem is a directory.
+     * does nothing if the tablespace already exists there.
+     */
+    pub fn create_tablespace(&self, name: &str, location: &str) -> Result<(), Error> {
+        let mut tablespaces = self.tablespaces.write().unwrap();
+        match tablespaces.catalog.location(name) {
+            Some(l) if l == location => return Ok(()),
+            Some(l) => {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("tablespace {} is already at {}", name, l),
+                ))
+            }
+            None => {}
+        }
+        let mut catalog = tablespaces.catalog.clone();
+        catalog.add(name, location)?;
+        let storage = self.storage.open_tablespace(location)?;
+        tablespaces.check_separate(storage.as_ref(), location)?;
+        tablespaces.catalog = catalog;
+        tablespaces.catalog.store(self.storage.as_ref())?;
+        tablespaces.storages.insert(name.to_string(), storage);
+        Ok(())
+    }
+
+    // (name, location) of every tablespace but the default one
+    pub fn tablespaces(&self) -> Vec<(String, String)> {
+        let tablespaces = self.tablespaces.read().unwrap();
+        tablespaces
+            .catalog
+            .tablespaces()
+            .map(|(name, location)| (name.to_string(), location.to_string()))
+            .collect()
+    }
+
+    pub fn tablespace_of(&self, filename: &str) -> String {
+        self.tablespaces
+            .read()
+            .unwrap()
+            .name_of(filename)
+            .to_string()
+    }
+
+    /**
+     * places the file, with its free list and block map, in the tablespace and moves the
+     * blocks it already has there. no I/O on the file may run while it is being moved.
+     * the copy is synced before the catalog is updated and the old file removed, so a crash
+     * leaves the file in one place or the other.
+     */
+    pub fn set_tablespace(&self, filename: &str, tablespace: &str) -> Result<(), Error> {
+        let mut tablespaces = self.tablespaces.write().unwrap();
+        let to = tablespaces.storage(tablespace)?;
+        if tablespaces.name_of(filename) == tablespace {
+            return Ok(());
+        }
+        let from = tablespaces.storage(tablespaces.name_of(filename))?;
+        let files = [
+            filename.to_string(),
+            free_list_file(filename),
+            block_map_file(filename),
+        ];
+        for name in &files {
+            copy_file(from.as_ref(), to.as_ref(), name)?;
+        }
+        to.sync_directory()?;
+        tablespaces.catalog.place(filename, tablespace)?;
+        tablespaces.catalog.store(self.storage.as_ref())?;
+        for name in &files {
+            from.delete(name)?;
+        }
+        from.sync_directory()?;
+        self.synced_files.lock().unwrap().remove(filename);
+        Ok(())
+    }
+
+    // storage of the tablespace the file lives in, free lists and block maps stay with their file
+    fn storage_for(&self, filename: &str) -> Arc<dyn StorageBackend> {
+        let tablespaces = self.tablespaces.read().unwrap();
+        tablespaces.storages[tablespaces.name_of(filename)].clone()
+    }
+
+    fn free_list<'a>(
+        &self,
+        free_lists: &'a mut HashMap<String, FreeList>,
+        filename: &str,
+    ) -> Result<&'a mut FreeList, Error> {
+        if !free_lists.contains_key(filename) {
+            let free_list = FreeList::load(self.storage_for(filename).as_ref(), filename)?;
+            free_lists.insert(filename.to_string(), free_list);
+        }
+        Ok(free_lists.get_mut(filename).unwrap())
+    }
+
+    fn block_map<'a>(
+        &self,
+        block_maps: &'a mut HashMap<String, BlockMap>,
+        filename: &str,
+    ) -> Result<&'a mut BlockMap, Error> {
+        if !block_maps.contains_key(filename) {
+            let block_map = BlockMap::load(self.storage_for(filename).as_ref(), filename)?;
+            block_maps.insert(filename.to_string(), block_map);
+        }
+        Ok(block_maps.get_mut(filename).unwrap())
+    }
+
+    // reads the image of a block of a compressed file, returning it and the number of bytes read
+    fn read_mapped(&self, blk: &BlockId) -> Result<(Vec<u8>, usize), Error> {
+        let entry = {
+            let mut block_maps = self.block_maps.lock().unwrap();
+            self.block_map(&mut block_maps, blk.filename())?
+                .get(blk.number())
+        };
+        if entry.len == 0 {
+            return Ok((vec![0; self.physical_block_size()], 0));
+        }
+        let mut b = vec![0; entry.len as usize];
+        let read_len =
+            self.storage_for(blk.filename())
+                .read_at(blk.filename(), entry.offset, &mut b)?;
+        Ok((b, read_len))
+    }
+
+    /**
+     * writes a block image. in a compressed file the image goes to a free slot and the map is
+     * switched to it afterwards, so a write that is cut short never damages the old image.
+     * the old slot is reused once the map is synced. blocks of the log are written over their
+     * old image instead, like in an uncompressed file, see is_log.
+     */
+    fn write_physical_block(&self, blk: &BlockId, b: &[u8]) -> Result<(), Error> {
+        self.count_written(self.physical_block_size(), b.len());
+        let start = Instant::now();
+        let storage = self.storage_for(blk.filename());
+        if !self.is_compressed() {
+            storage.write_block(blk.filename(), blk.number(), b)?;
+            self.counters(blk.filename())
+                .record_write(1, b.len(), start.elapsed());
+            return Ok(());
+        }
+        let mut block_maps = self.block_maps.lock().unwrap();
+        let block_map = self.block_map(&mut block_maps, blk.filename())?;
+        let old = block_map.get(blk.number());
+        if self.is_log(blk.filename()) && old.capacity as usize >= b.len() {
+            storage.write_at(blk.filename(), old.offset, b)?;
+        } else {
+            let entry = block_map.allocate(b.len());
+            storage.write_at(blk.filename(), entry.offset, b)?;
+            block_map.set(storage.as_ref(), blk.filename(), blk.number(), entry)?;
+            self.release_slots(block_map);
+        }
+        self.counters(blk.filename())
+            .record_write(1, b.len(), start.elapsed());
+        Ok(())
+    }
+
+    // without durability nothing survives a crash anyway, so slots let go are reused right away
+    fn release_slots(&self, block_map: &mut BlockMap) {
+        if self.durability == Durability::None {
+            block_map.reuse_released();
+        }
+    }
+
+    // writes the block as one that was never written, which reads back as an empty page
+    fn clear_block(&self, blk: &BlockId) -> Result<(), Error> {
+        if !self.is_compressed() {
+            return self.write_physical_block(blk, &vec![0; self.physical_block_size()]);
+        }
+        let mut block_maps = self.block_maps.lock().unwrap();
+        let block_map = self.block_map(&mut block_maps, blk.filename())?;
+        block_map.set(
+            self.storage_for(blk.filename()).as_ref(),
+            blk.filename(),
+            blk.number(),
+            MapEntry::default(),
+        )?;
+        self.release_slots(block_map);
+        Ok(())
+    }
+
+    fn count_written(&self, block_bytes: usize, stored_bytes: usize) {
+        self.block_bytes_written
+            .fetch_add(block_bytes as u64, Ordering::Relaxed);
+        self.stored_bytes_written
+            .fetch_add(stored_bytes as u64, Ordering::Relaxed);
+    }
+
+    /**
+     * forces the blocks written to the file to stable storage, as far as the durability setting asks for.
+     * the first sync of a file also syncs the directory so that a newly created file survives a crash.
+     */
+    pub fn sync(&self, filename: &str) -> Result<(), Error> {
+        let mode = match self.durability {
+            Durability::None => return Ok(()),
+            Durability::DataSync => SyncMode::Data,
+            Durability::FullSync => SyncMode::Full,
+        };
+        let storage = self.storage_for(filename);
+        storage.sync(filename, mode)?;
+        if self.is_compressed() {
+            // the slots the synced map no longer points at can be written over now
+            let mut block_maps = self.block_maps.lock().unwrap();
+            let block_map = self.block_map(&mut block_maps, filename)?;
+            storage.sync(&block_map_file(filename), mode)?;
+            block_map.reuse_released();
+        }
+        match mode {
+            SyncMode::Data => self.data_syncs.fetch_add(1, Ordering::Relaxed),
+            SyncMode::Full => self.full_syncs.fetch_add(1, Ordering::Relaxed),
+        };
+        self.counters(filename).record_sync();
+        let mut synced_files = self.synced_files.lock().unwrap();
+        if !synced_files.contains(filename) {
+            storage.sync_directory()?;
+            self.directory_syncs.fetch_add(1, Ordering::Relaxed);
+            synced_files.insert(filename.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    pub fn sync_stats(&self) -> SyncStats {
+        SyncStats {
+            data_syncs: self.data_syncs.load(Ordering::Relaxed),
+            full_syncs: self.full_syncs.load(Ordering::Relaxed),
+            directory_syncs: self.directory_syncs.load(Ordering::Relaxed),
+        }
+    }
+
+    /**
+     * returns the I/O done per file since the database was opened or the stats were reset,
+     * together with the sync and compression counters.
+     */
+    pub fn stats(&self) -> IoStats {
+        let file_kinds = self.file_kinds.lock().unwrap();
+        let files = self
+            .counters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(filename, counters)| {
+                let kind = kind_of(&file_kinds, filename);
+                (filename.clone(), (kind, counters.snapshot()))
+            })
+            .collect();
+        IoStats {
+            files,
+            sync: self.sync_stats(),
+            compression: self.compression_stats(),
+        }
+    }
+
+    // sets every counter back to zero
+    pub fn reset_stats(&self) {
+        self.counters.write().unwrap().clear();
+        for counter in [
+            &self.data_syncs,
+            &self.full_syncs,
+            &self.directory_syncs,
+            &self.block_bytes_written,
+            &self.stored_bytes_written,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /**
+     * marks a file as not holding data, so that its I/O is told apart in the stats.
+     * a log file has to be marked before it is first written, see is_log.
+     */
+    pub fn set_file_kind(&self, filename: &str, kind: FileKind) {
+        self.file_kinds
+            .lock()
+            .unwrap()
+            .insert(filename.to_string(), kind);
+    }
+
+    pub fn file_kind(&self, filename: &str) -> FileKind {
+        kind_of(&self.file_kinds.lock().unwrap(), filename)
+    }
+
+    /**
+     * whether the file is part of the log. a torn write of the last log block has to leave the
+     * records written before in place so that recovery can salvage them, so even in a compressed
+     * database log blocks are stored whole and rewritten in their slot.
+     */
+    fn is_log(&self, filename: &str) -> bool {
+        self.file_kind(filename) == FileKind::Log
+    }
+
+    fn counters(&self, filename: &str) -> Arc<FileCounters> {
+        if let Some(counters) = self.counters.read().unwrap().get(filename) {
+            return counters.clone();
+        }
+        self.counters
+            .write()
+            .unwrap()
+            .entry(filename.to_string())
+            .or_default()
+            .clone()
+    }
+
+    pub fn compression_stats(&self) -> CompressionStats {
+        CompressionStats {
+            block_bytes: self.block_bytes_written.load(Ordering::Relaxed),
+            stored_bytes: self.stored_bytes_written.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.superblock.is_compressed()
+    }
+
+    /**
+     * returns the name of a new temp file, which goes to the temp tablespace and is deleted
+     * the next time the database is opened. leftovers of earlier runs are gone by then,
+     * so the names start over on every open.
+     */
+    pub fn temp_file(&self) -> String {
+        let n = self.next_temp.fetch_add(1, Ordering::Relaxed);
+        format!("{}{}", TEMP_PREFIX, n)
+    }
+
     pub fn is_new(&self) -> bool {
         self.is_new
     }
+
+    pub fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
     pub fn length(&self, file_name: &str) -> Result<u64, Error> {
-        let file_io = self.get_file(file_name)?;
-        let file_io = file_io.lock().unwrap();
-        let metadata = file_io.metadata().unwrap();
-        Ok(metadata.len() / self.block_size as u64)
+        if self.is_compressed() {
+            let mut block_maps = self.block_maps.lock().unwrap();
+            return Ok(self.block_map(&mut block_maps, file_name)?.len());
+        }
+        Ok(self.storage_for(file_name).length(file_name)? / self.physical_block_size() as u64)
     }
 
     pub fn block_size(&self) -> usize {
         self.block_size
     }
 
-    fn get_file(&self, filename: &str) -> Result<Arc<Mutex<fs::File>>, Error> {
-        let path = self.db_directory.join(filename);
-        let mut open_files = self.open_files.lock().unwrap();
-        if let Some(file) = open_files.get(&path) {
-            return Ok(file.clone());
+    // size of a block on disk, including its header
+    pub fn physical_block_size(&self) -> usize {
+        self.header_size() + self.block_size
+    }
+
+    // an encrypted block carries its nonce after the header
+    fn header_size(&self) -> usize {
+        match self.encryption_key {
+            Some(_) => HEADER_SIZE + NONCE_SIZE,
+            None => HEADER_SIZE,
+        }
+    }
+
+    /**
+     * hands out a nonce no block has been encrypted under before. when the nonces reserved
+     * in the superblock run out, the next batch is reserved there first.
+     */
+    fn next_nonce(&self) -> Result<u64, Error> {
+        let mut nonces = self.nonces.lock().unwrap();
+        if nonces.next == nonces.limit {
+            let limit = nonces.limit + NONCE_BATCH;
+            self.superblock
+                .store_nonce_limit(self.storage.as_ref(), limit)?;
+            nonces.limit = limit;
+        }
+        nonces.next += 1;
+        Ok(nonces.next - 1)
+    }
+
+    // checks a block read from storage, of which read_len bytes existed, and copies it into the page
+    fn load_physical_block(
+        &self,
+        blk: &BlockId,
+        block: &[u8],
+        read_len: usize,
+        page: &mut Page,
+    ) -> Result<(), Error> {
+        if read_len != 0 && read_len != block.len() {
+            return Err(CorruptBlockError::TornWrite {
+                block: blk.clone(),
+                read_len,
+                expected_len: block.len(),
+            }
+            .into());
+        }
+        let header = BlockHeader::verify(blk, block)?;
+        let mut contents = block[self.header_size()..].to_vec();
+        if let Some(key) = &self.encryption_key {
+            if !is_unwritten(block) {
+                key.apply(read_nonce(block), &mut contents);
+            }
+        }
+        if header.flags & FLAG_COMPRESSED != 0 {
+            contents = decompress(&contents, self.block_size)
+                .filter(|c| c.len() == self.block_size)
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, format!("cannot decompress {}", blk))
+                })?;
+        }
+        page.byte_buffer = contents;
+        page.set_lsn(header.lsn);
+        Ok(())
+    }
+
+    // builds the image of a block: the header followed by the page contents, compressed if that saves space
+    fn to_physical_block(&self, filename: &str, page: &Page) -> Result<Vec<u8>, Error> {
+        let contents = &page.byte_buffer[0..self.block_size()];
+        let mut b = vec![0; self.header_size()];
+        let mut flags = 0;
+        let compressed = self.is_compressed() && !self.is_log(filename);
+        match compressed.then(|| compress(contents)) {
+            Some(compressed) if compressed.len() < contents.len() => {
+                flags |= FLAG_COMPRESSED;
+                b.extend_from_slice(&compressed);
+            }
+            _ => b.extend_from_slice(contents),
+        }
+        if let Some(key) = &self.encryption_key {
+            let nonce = self.next_nonce()?;
+            b[HEADER_SIZE..HEADER_SIZE + NONCE_SIZE].copy_from_slice(&nonce.to_le_bytes());
+            key.apply(nonce, &mut b[HEADER_SIZE + NONCE_SIZE..]);
+        }
+        BlockHeader {
+            flags,
+            lsn: page.lsn(),
         }
-        let arc_file = Arc::new(Mutex::new(
-            OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(path.clone())?,
+        .stamp(&mut b);
+        Ok(b)
+    }
+}
+
+// the nonce an encrypted block was written with
+fn read_nonce(block: &[u8]) -> u64 {
+    u64::from_le_bytes(
+        block[HEADER_SIZE..HEADER_SIZE + NONCE_SIZE]
+            .try_into()
+            .unwrap(),
+    )
+}
+
+pub fn is_temp_file(filename: &str) -> bool {
+    filename.starts_with(TEMP_PREFIX)
+}
+
+// the file a free list or block map belongs to, the file itself otherwise
+fn owner_file(filename: &str) -> &str {
+    filename
+        .strip_suffix(FREE_LIST_SUFFIX)
+        .or_else(|| filename.strip_suffix(BLOCK_MAP_SUFFIX))
+        .unwrap_or(filename)
+}
+
+// copies a file to another storage, replacing what was there under its name, and syncs the copy
+fn copy_file(
+    from: &dyn StorageBackend,
+    to: &dyn StorageBackend,
+    filename: &str,
+) -> Result<(), Error> {
+    // deleting the copy first would delete the file itself
+    if from.identity()? == to.identity()? {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("cannot copy {} onto itself", filename),
         ));
-        open_files.insert(path, arc_file.clone());
-        Ok(arc_file)
     }
+    to.delete(filename)?;
+    let length = from.length(filename)?;
+    let mut buf = vec![0; COPY_CHUNK];
+    let mut offset = 0;
+    while offset < length {
+        let read_len = from.read_at(filename, offset, &mut buf)?;
+        if read_len == 0 {
+            break;
+        }
+        to.write_at(filename, offset, &buf[..read_len])?;
+        offset += read_len as u64;
+    }
+    if length > 0 {
+        to.sync(filename, SyncMode::Full)?;
+    }
+    Ok(())
+}
+
+fn kind_of(file_kinds: &HashMap<String, FileKind>, filename: &str) -> FileKind {
+    match file_kinds.get(filename) {
+        Some(kind) => *kind,
+        None if is_temp_file(filename) => FileKind::Temp,
+        None => FileKind::Data,
+    }
+}
+
+// splits blks into [start, end) ranges of consecutive blocks of the same file
+fn consecutive_runs(blks: &[BlockId]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=blks.len() {
+        let continues = i < blks.len()
+            && blks[i].filename() == blks[i - 1].filename()
+            && blks[i].number() == blks[i - 1].number() + 1;
+        if !continues {
+            runs.push((start, i));
+            start = i;
+        }
+    }
+    runs
 }
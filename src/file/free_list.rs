@@ -0,0 +1,67 @@
+use std::{collections::BTreeSet, io::Error, mem::size_of};
+
+use crate::file::storage::StorageBackend;
+
+// The free blocks of a file are kept next to it in a file with this suffix,
+// as a list of little endian u64 block numbers.
+pub const FREE_LIST_SUFFIX: &str = ".free";
+
+pub fn free_list_file(filename: &str) -> String {
+    format!("{}{}", filename, FREE_LIST_SUFFIX)
+}
+
+// Blocks of a file that were freed and can be handed out again by FileManager::append.
+#[derive(Debug, Default)]
+pub struct FreeList {
+    blocks: BTreeSet<u64>,
+}
+
+impl FreeList {
+    pub fn load(storage: &dyn StorageBackend, filename: &str) -> Result<Self, Error> {
+        let list_file = free_list_file(filename);
+        let mut b = vec![0; storage.length(&list_file)? as usize];
+        if !b.is_empty() {
+            storage.read_block(&list_file, 0, &mut b)?;
+        }
+        let blocks = b
+            .chunks_exact(size_of::<u64>())
+            .map(|n| u64::from_le_bytes(n.try_into().unwrap()))
+            .collect();
+        Ok(Self { blocks })
+    }
+
+    pub fn store(&self, storage: &dyn StorageBackend, filename: &str) -> Result<(), Error> {
+        let list_file = free_list_file(filename);
+        let b: Vec<u8> = self.blocks.iter().flat_map(|n| n.to_le_bytes()).collect();
+        if !b.is_empty() {
+            storage.write_block(&list_file, 0, &b)?;
+        }
+        storage.truncate(&list_file, b.len() as u64)
+    }
+
+    // returns false if the block was already free
+    pub fn insert(&mut self, blk_num: u64) -> bool {
+        self.blocks.insert(blk_num)
+    }
+
+    pub fn remove(&mut self, blk_num: u64) -> bool {
+        self.blocks.remove(&blk_num)
+    }
+
+    // takes the lowest free block, keeping files dense at the front
+    pub fn take(&mut self) -> Option<u64> {
+        self.blocks.pop_first()
+    }
+
+    pub fn contains(&self, blk_num: u64) -> bool {
+        self.blocks.contains(&blk_num)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use crate::file::encryption::EncryptionKey;
+
+// How hard FileManager tries to get writes onto stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    // writes stay in the operating system cache and may be lost on power failure
+    None,
+    // fdatasync: file contents reach stable storage, metadata only if needed to read them back
+    DataSync,
+    // fsync: file contents and metadata reach stable storage
+    #[default]
+    FullSync,
+}
+
+// Settings for the file layer and the log, passed to SimpleDB when a database is opened.
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    pub durability: Durability,
+    // most files the filesystem backend keeps open, the least recently used one is closed first
+    pub max_open_files: usize,
+    // encrypts every block written through FileManager, the database has to be reopened with the same key
+    pub encryption_key: Option<EncryptionKey>,
+    // stores blocks compressed; fixed when the database is created, later opens use the stored setting
+    pub compression: bool,
+    // serves reads from a memory mapping of each file instead of read calls, unix only
+    pub mmap_reads: bool,
+    // splits files of the filesystem backend into segment files of this many bytes, 0 to keep them whole;
+    // fixed when the database is created
+    pub segment_size: u64,
+    // tablespaces to create on open if they do not exist yet, as (name, location) pairs
+    pub tablespaces: Vec<(String, String)>,
+    // tablespace that temp files are placed in, the default one if None
+    pub temp_tablespace: Option<String>,
+    // how long the leader of a group commit waits for more committers before it flushes the log
+    pub commit_delay: Duration,
+    // starts a new log segment file every this many blocks, 0 to keep the log in a single file;
+    // fixed when the database is created
+    pub log_segment_blocks: u64,
+    // where log segments that are no longer needed are moved to, they are deleted if None
+    pub log_archive: Option<String>,
+    // opens an existing database without changing any of its files, e.g. to inspect it;
+    // writes fail with PermissionDenied
+    pub read_only: bool,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            durability: Durability::default(),
+            max_open_files: 64,
+            encryption_key: None,
+            compression: false,
+            mmap_reads: false,
+            segment_size: 0,
+            tablespaces: Vec::new(),
+            temp_tablespace: None,
+            commit_delay: Duration::ZERO,
+            log_segment_blocks: 0,
+            log_archive: None,
+            read_only: false,
+        }
+    }
+}
@@ -1,7 +1,51 @@
-use std::{mem::size_of, string::FromUtf8Error};
+use std::{
+    error, fmt,
+    mem::size_of,
+    string::FromUtf8Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PageError {
+    // the value at offset with len bytes does not fit into the page
+    OutOfBounds {
+        offset: usize,
+        len: usize,
+        page_size: usize,
+    },
+    // a bool is stored as 0 or 1
+    InvalidBool(u8),
+    // a timestamp does not fit into an i64 of microseconds since the unix epoch
+    TimestampOutOfRange,
+    // a string is not valid UTF-8
+    InvalidUtf8(FromUtf8Error),
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PageError::OutOfBounds {
+                offset,
+                len,
+                page_size,
+            } => write!(
+                f,
+                "{} bytes at offset {} do not fit into a page of {} bytes",
+                len, offset, page_size
+            ),
+            PageError::InvalidBool(b) => write!(f, "{} is not a valid bool", b),
+            PageError::TimestampOutOfRange => write!(f, "timestamp out of range"),
+            PageError::InvalidUtf8(e) => write!(f, "invalid string: {}", e),
+        }
+    }
+}
+
+impl error::Error for PageError {}
 
 pub struct Page {
     pub byte_buffer: Vec<u8>,
+    // lsn of the most recent log record that modified this page, stored in the block header
+    lsn: u64,
 }
 
 impl Page {
@@ -9,12 +53,14 @@ impl Page {
     pub fn new(block_size: usize) -> Self {
         Self {
             byte_buffer: vec![0; block_size],
+            lsn: 0,
         }
     }
 
     pub fn from_bytes(b: &[u8]) -> Self {
         Self {
             byte_buffer: b.to_vec(),
+            lsn: 0,
         }
     }
 
@@ -62,6 +108,153 @@ impl Page {
     pub fn contents(&self) -> &[u8] {
         &self.byte_buffer
     }
+
+    // the try_ accessors fail with a PageError where the ones above panic, e.g. on a value
+    // that does not fit into the page
+    pub fn try_get_i16(&self, offset: usize) -> Result<i16, PageError> {
+        Ok(i16::from_le_bytes(self.read(offset)?))
+    }
+
+    pub fn try_set_i16(&mut self, offset: usize, n: i16) -> Result<(), PageError> {
+        self.write(offset, &n.to_le_bytes())
+    }
+
+    pub fn try_get_i32(&self, offset: usize) -> Result<i32, PageError> {
+        Ok(i32::from_le_bytes(self.read(offset)?))
+    }
+
+    pub fn try_set_i32(&mut self, offset: usize, n: i32) -> Result<(), PageError> {
+        self.write(offset, &n.to_le_bytes())
+    }
+
+    pub fn try_get_u32(&self, offset: usize) -> Result<u32, PageError> {
+        Ok(u32::from_le_bytes(self.read(offset)?))
+    }
+
+    pub fn try_set_u32(&mut self, offset: usize, n: u32) -> Result<(), PageError> {
+        self.write(offset, &n.to_le_bytes())
+    }
+
+    pub fn try_get_i64(&self, offset: usize) -> Result<i64, PageError> {
+        Ok(i64::from_le_bytes(self.read(offset)?))
+    }
+
+    pub fn try_set_i64(&mut self, offset: usize, n: i64) -> Result<(), PageError> {
+        self.write(offset, &n.to_le_bytes())
+    }
+
+    pub fn try_get_u64(&self, offset: usize) -> Result<u64, PageError> {
+        Ok(u64::from_le_bytes(self.read(offset)?))
+    }
+
+    pub fn try_set_u64(&mut self, offset: usize, n: u64) -> Result<(), PageError> {
+        self.write(offset, &n.to_le_bytes())
+    }
+
+    pub fn try_get_f64(&self, offset: usize) -> Result<f64, PageError> {
+        Ok(f64::from_le_bytes(self.read(offset)?))
+    }
+
+    pub fn try_set_f64(&mut self, offset: usize, n: f64) -> Result<(), PageError> {
+        self.write(offset, &n.to_le_bytes())
+    }
+
+    pub fn try_get_bool(&self, offset: usize) -> Result<bool, PageError> {
+        match self.read::<1>(offset)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [b] => Err(PageError::InvalidBool(b)),
+        }
+    }
+
+    pub fn try_set_bool(&mut self, offset: usize, b: bool) -> Result<(), PageError> {
+        self.write(offset, &[b as u8])
+    }
+
+    // fixed width bytes are stored without a length, the caller knows how many to read
+    pub fn try_get_fixed_bytes(&self, offset: usize, len: usize) -> Result<&[u8], PageError> {
+        self.check_bounds(offset, len)?;
+        Ok(&self.byte_buffer[offset..offset + len])
+    }
+
+    pub fn try_set_fixed_bytes(&mut self, offset: usize, b: &[u8]) -> Result<(), PageError> {
+        self.write(offset, b)
+    }
+
+    // bytes are stored after their length, like with set_bytes
+    pub fn try_get_bytes(&self, offset: usize) -> Result<&[u8], PageError> {
+        let len = self.try_get_u32(offset)? as usize;
+        self.try_get_fixed_bytes(offset + size_of::<u32>(), len)
+    }
+
+    pub fn try_set_bytes(&mut self, offset: usize, b: &[u8]) -> Result<(), PageError> {
+        let len = u32::try_from(b.len()).map_err(|_| PageError::OutOfBounds {
+            offset,
+            len: b.len(),
+            page_size: self.byte_buffer.len(),
+        })?;
+        self.check_bounds(offset, Self::max_length(b.len()))?;
+        self.try_set_u32(offset, len)?;
+        self.write(offset + size_of::<u32>(), b)
+    }
+
+    pub fn try_get_string(&self, offset: usize) -> Result<String, PageError> {
+        String::from_utf8(self.try_get_bytes(offset)?.to_vec()).map_err(PageError::InvalidUtf8)
+    }
+
+    pub fn try_set_string(&mut self, offset: usize, s: &str) -> Result<(), PageError> {
+        self.try_set_bytes(offset, s.as_bytes())
+    }
+
+    // a timestamp is stored as an i64 of microseconds since the unix epoch, negative before it
+    pub fn try_get_timestamp(&self, offset: usize) -> Result<SystemTime, PageError> {
+        let micros = self.try_get_i64(offset)?;
+        let since_epoch = Duration::from_micros(micros.unsigned_abs());
+        let timestamp = if micros >= 0 {
+            UNIX_EPOCH.checked_add(since_epoch)
+        } else {
+            UNIX_EPOCH.checked_sub(since_epoch)
+        };
+        timestamp.ok_or(PageError::TimestampOutOfRange)
+    }
+
+    pub fn try_set_timestamp(&mut self, offset: usize, t: SystemTime) -> Result<(), PageError> {
+        let micros = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => i64::try_from(d.as_micros()),
+            Err(e) => i64::try_from(e.duration().as_micros()).map(|m| -m),
+        };
+        self.try_set_i64(offset, micros.map_err(|_| PageError::TimestampOutOfRange)?)
+    }
+
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    pub fn set_lsn(&mut self, lsn: u64) {
+        self.lsn = lsn;
+    }
+
+    fn check_bounds(&self, offset: usize, len: usize) -> Result<(), PageError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.byte_buffer.len() => Ok(()),
+            _ => Err(PageError::OutOfBounds {
+                offset,
+                len,
+                page_size: self.byte_buffer.len(),
+            }),
+        }
+    }
+
+    fn read<const N: usize>(&self, offset: usize) -> Result<[u8; N], PageError> {
+        self.check_bounds(offset, N)?;
+        Ok(self.byte_buffer[offset..offset + N].try_into().unwrap())
+    }
+
+    fn write(&mut self, offset: usize, b: &[u8]) -> Result<(), PageError> {
+        self.check_bounds(offset, b.len())?;
+        self.byte_buffer[offset..offset + b.len()].copy_from_slice(b);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -86,4 +279,78 @@ mod tests {
             "Hello, world! This is a longer string.Hello, world! This is a longer string.Hello, world! This is a longer string"
         );
     }
+
+    #[test]
+    fn test_typed_accessors() {
+        let mut page = Page::new(64);
+        page.try_set_i16(0, -2).unwrap();
+        page.try_set_i32(2, -70000).unwrap();
+        page.try_set_i64(6, i64::MIN).unwrap();
+        page.try_set_u64(14, u64::MAX).unwrap();
+        page.try_set_f64(22, 3.25).unwrap();
+        page.try_set_bool(30, true).unwrap();
+        page.try_set_fixed_bytes(31, b"abc").unwrap();
+        let t = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        page.try_set_timestamp(34, t).unwrap();
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(86400);
+        page.try_set_timestamp(42, before_epoch).unwrap();
+
+        assert_eq!(page.try_get_i16(0).unwrap(), -2);
+        assert_eq!(page.try_get_i32(2).unwrap(), -70000);
+        assert_eq!(page.try_get_i64(6).unwrap(), i64::MIN);
+        assert_eq!(page.try_get_u64(14).unwrap(), u64::MAX);
+        assert_eq!(page.try_get_f64(22).unwrap(), 3.25);
+        assert!(page.try_get_bool(30).unwrap());
+        assert_eq!(page.try_get_fixed_bytes(31, 3).unwrap(), b"abc");
+        assert_eq!(page.try_get_timestamp(34).unwrap(), t);
+        assert_eq!(page.try_get_timestamp(42).unwrap(), before_epoch);
+
+        assert_eq!(page.try_get_bool(31), Err(PageError::InvalidBool(b'a')));
+        assert_eq!(
+            page.try_set_u64(60, 1),
+            Err(PageError::OutOfBounds {
+                offset: 60,
+                len: 8,
+                page_size: 64
+            })
+        );
+        assert!(page.try_get_fixed_bytes(usize::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn test_checked_strings() {
+        let mut page = Page::new(32);
+        page.try_set_u32(0, u32::MAX).unwrap();
+        assert_eq!(page.try_get_u32(0).unwrap(), u32::MAX);
+        assert!(page.try_get_u32(30).is_err());
+
+        page.try_set_string(4, "checked").unwrap();
+        assert_eq!(page.try_get_string(4).unwrap(), "checked");
+        assert_eq!(page.try_get_bytes(4).unwrap(), b"checked");
+        assert_eq!(page.get_string(4).unwrap(), "checked");
+
+        // the string would end past the page, nothing is written
+        assert_eq!(
+            page.try_set_string(20, "too long to fit"),
+            Err(PageError::OutOfBounds {
+                offset: 20,
+                len: 19,
+                page_size: 32
+            })
+        );
+        assert_eq!(page.try_get_u32(20).unwrap(), 0);
+
+        // a length reaching past the page or bytes that are not UTF-8 are errors, not panics
+        page.try_set_u32(0, 1000).unwrap();
+        assert!(matches!(
+            page.try_get_bytes(0),
+            Err(PageError::OutOfBounds { len: 1000, .. })
+        ));
+        page.try_set_bytes(16, &[0xff, 0xfe]).unwrap();
+        assert!(matches!(
+            page.try_get_string(16),
+            Err(PageError::InvalidUtf8(_))
+        ));
+        assert!(page.try_get_string(usize::MAX).is_err());
+    }
 }
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::file::file_manager::{CompressionStats, SyncStats};
+
+// What a file holds, so that statistics can tell the log from tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileKind {
+    Data,
+    Temp,
+    Log,
+}
+
+// number of latency buckets, the last one takes everything from about 4 seconds on
+const BUCKETS: usize = 24;
+
+// Counts of operations by latency. Bucket 0 holds operations that took less than 1us,
+// bucket i those that took from 2^(i-1) up to 2^i microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; BUCKETS],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; BUCKETS],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    // upper bound of the latency of bucket i
+    pub fn bucket_bound(i: usize) -> Duration {
+        Duration::from_micros(1 << i)
+    }
+
+    /**
+     * returns an upper bound of the latency below which the fraction p of operations completed,
+     * e.g. percentile(0.99) for the 99th percentile. None if nothing was recorded.
+     */
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * p).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(Self::bucket_bound(i));
+            }
+        }
+        None
+    }
+
+    fn merge(&mut self, other: &LatencyHistogram) {
+        for (a, b) in self.buckets.iter_mut().zip(other.buckets) {
+            *a += b;
+        }
+    }
+}
+
+// I/O done by FileManager on a file, or on a group of files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub appends: u64,
+    pub syncs: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub read_latency: LatencyHistogram,
+    // writes and appends
+    pub write_latency: LatencyHistogram,
+}
+
+impl FileStats {
+    fn merge(&mut self, other: &FileStats) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.appends += other.appends;
+        self.syncs += other.syncs;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.read_latency.merge(&other.read_latency);
+        self.write_latency.merge(&other.write_latency);
+    }
+}
+
+// A snapshot of the statistics of a FileManager, see FileManager::stats.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IoStats {
+    pub files: BTreeMap<String, (FileKind, FileStats)>,
+    pub sync: SyncStats,
+    pub compression: CompressionStats,
+}
+
+impl IoStats {
+    pub fn file(&self, filename: &str) -> FileStats {
+        self.files
+            .get(filename)
+            .map(|(_, stats)| *stats)
+            .unwrap_or_default()
+    }
+
+    pub fn by_kind(&self) -> BTreeMap<FileKind, FileStats> {
+        let mut by_kind = BTreeMap::new();
+        for (kind, stats) in self.files.values() {
+            by_kind
+                .entry(*kind)
+                .or_insert_with(FileStats::default)
+                .merge(stats);
+        }
+        by_kind
+    }
+
+    pub fn total(&self) -> FileStats {
+        let mut total = FileStats::default();
+        for (_, stats) in self.files.values() {
+            total.merge(stats);
+        }
+        total
+    }
+}
+
+// The live counters of a file, updated without locking.
+#[derive(Default)]
+pub struct FileCounters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    appends: AtomicU64,
+    syncs: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    read_latency: AtomicHistogram,
+    write_latency: AtomicHistogram,
+}
+
+impl FileCounters {
+    pub fn record_read(&self, blocks: u64, bytes: usize, latency: Duration) {
+        self.reads.fetch_add(blocks, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.read_latency.record(latency);
+    }
+
+    pub fn record_write(&self, blocks: u64, bytes: usize, latency: Duration) {
+        self.writes.fetch_add(blocks, Ordering::Relaxed);
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.write_latency.record(latency);
+    }
+
+    pub fn record_append(&self, bytes: usize, latency: Duration) {
+        self.appends.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.write_latency.record(latency);
+    }
+
+    pub fn record_sync(&self) {
+        self.syncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> FileStats {
+        FileStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            appends: self.appends.load(Ordering::Relaxed),
+            syncs: self.syncs.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            read_latency: self.read_latency.snapshot(),
+            write_latency: self.write_latency.snapshot(),
+        }
+    }
+}
+
+struct AtomicHistogram {
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl AtomicHistogram {
+    fn record(&self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        let i = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[i.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_histogram() {
+        let histogram = AtomicHistogram::default();
+        for micros in [0, 1, 3, 3, 100, 1 << 30] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 6);
+        assert_eq!(snapshot.buckets[0], 1);
+        assert_eq!(snapshot.buckets[2], 2);
+        assert_eq!(snapshot.buckets[BUCKETS - 1], 1);
+        assert_eq!(snapshot.percentile(0.5), Some(Duration::from_micros(4)));
+        assert_eq!(snapshot.percentile(0.8), Some(Duration::from_micros(128)));
+        assert_eq!(LatencyHistogram::default().percentile(0.5), None);
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::Error;
+use std::sync::Arc;
+
+pub mod faulty_backend;
+pub mod fs_backend;
+pub mod memory_backend;
+#[cfg(unix)]
+pub mod mmap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    // contents only, like fdatasync
+    Data,
+    // contents and metadata, like fsync
+    Full,
+}
+
+// Where FileManager keeps its files. Files are addressed by name and read and written in
+// whole blocks, the block size being the length of the buffer passed in.
+pub trait StorageBackend: Send + Sync {
+    /**
+     * reads block blk_num into buf and returns the number of bytes read,
+     * which is 0 if the block lies past the end of the file.
+     */
+    fn read_block(&self, filename: &str, blk_num: u64, buf: &mut [u8]) -> Result<usize, Error>;
+
+    fn write_block(&self, filename: &str, blk_num: u64, buf: &[u8]) -> Result<(), Error>;
+
+    /**
+     * reads from the byte offset into buf and returns the number of bytes read,
+     * which is less than the length of buf if the file ends first.
+     * used for data that is not laid out in whole blocks, like compressed block images.
+     */
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize, Error>;
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<(), Error>;
+
+    /**
+     * reads the consecutive blocks starting at blk_num that fit into buf and returns the
+     * number of bytes read. backends that can should do this in a single call.
+     */
+    fn read_blocks(
+        &self,
+        filename: &str,
+        blk_num: u64,
+        block_len: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut read_len = 0;
+        for (i, block) in buf.chunks_mut(block_len).enumerate() {
+            let n = self.read_block(filename, blk_num + i as u64, block)?;
+            read_len += n;
+            if n < block_len {
+                break;
+            }
+        }
+        Ok(read_len)
+    }
+
+    // writes the consecutive blocks in buf starting at blk_num
+    fn write_blocks(
+        &self,
+        filename: &str,
+        blk_num: u64,
+        block_len: usize,
+        buf: &[u8],
+    ) -> Result<(), Error> {
+        for (i, block) in buf.chunks(block_len).enumerate() {
+            self.write_block(filename, blk_num + i as u64, block)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * writes buf as a new block at the end of the file and returns its block number.
+     */
+    fn append(&self, filename: &str, buf: &[u8]) -> Result<u64, Error>;
+
+    /**
+     * returns the length of the file in bytes, 0 if it does not exist.
+     */
+    fn length(&self, filename: &str) -> Result<u64, Error>;
+
+    // forces previous writes to the file to stable storage
+    fn sync(&self, filename: &str, mode: SyncMode) -> Result<(), Error>;
+
+    // makes the creation and deletion of files durable
+    fn sync_directory(&self) -> Result<(), Error>;
+
+    // releases any handle kept open for the file; it is reopened on next use
+    fn close(&self, _filename: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // cuts the file down to len bytes
+    fn truncate(&self, filename: &str, len: u64) -> Result<(), Error>;
+
+    fn delete(&self, filename: &str) -> Result<(), Error>;
+
+    /**
+     * gives the file from the name to, replacing any file of that name in one step.
+     * the new name survives a crash once the directory is synced.
+     */
+    fn rename(&self, from: &str, to: &str) -> Result<(), Error>;
+
+    // names of all files in the storage
+    fn files(&self) -> Result<Vec<String>, Error>;
+
+    /**
+     * opens the storage of another tablespace, configured like this one.
+     * what the location means is up to the backend, for the filesystem it is a directory,
+     * taken to be inside the database directory if it is relative.
+     */
+    fn open_tablespace(&self, location: &str) -> Result<Arc<dyn StorageBackend>, Error>;
+
+    /**
+     * names where the files of the storage are kept, the same for two backends that share
+     * their files. for the filesystem it is the canonical path of the directory.
+     */
+    fn identity(&self) -> Result<String, Error>;
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{StorageBackend, SyncMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // the write fails with an error and nothing is written
+    Fail,
+    // only the first n bytes of the block reach the file, as if the machine died mid-write
+    Tear(usize),
+    // the write goes through, but only after this long, as on a slow disk
+    Delay(Duration),
+}
+
+// Wraps another backend and injects scripted I/O faults so that crash handling can be tested.
+// Writes are kept in memory until the file is synced; crash() throws away everything that
+// was not synced, and the database can then be reopened on the wrapped backend.
+pub struct FaultyBackend {
+    inner: Arc<dyn StorageBackend>,
+    state: Mutex<FaultState>,
+    // backends of the tablespaces opened from this one, by location; they crash along with it
+    tablespaces: Mutex<HashMap<String, Arc<FaultyBackend>>>,
+}
+
+#[derive(Default)]
+struct FaultState {
+    writes: u64,
+    // write number -> fault to inject on that write
+    faults: HashMap<u64, Fault>,
+    // unsynced contents of files written since their last sync
+    unsynced: HashMap<String, Vec<u8>>,
+    deleted: HashSet<String>,
+    // the next delete fails with an error and nothing is deleted
+    fail_delete: bool,
+}
+
+impl FaultyBackend {
+    pub fn new(inner: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(FaultState::default()),
+            tablespaces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // makes the nth write from now on fail, counting from 1
+    pub fn fail_nth_write(&self, n: u64) {
+        self.inject(n, Fault::Fail);
+    }
+
+    // makes the nth write from now on only write the first len bytes of the block
+    pub fn tear_nth_write(&self, n: u64, len: usize) {
+        self.inject(n, Fault::Tear(len));
+    }
+
+    // makes the next delete fail
+    pub fn fail_next_delete(&self) {
+        self.state.lock().unwrap().fail_delete = true;
+    }
+
+    pub fn inject(&self, n: u64, fault: Fault) {
+        let mut state = self.state.lock().unwrap();
+        let nth = state.writes + n;
+        state.faults.insert(nth, fault);
+    }
+
+    /**
+     * simulates a power failure: every write that has not been synced is lost.
+     * the durable state is what the wrapped backend holds.
+     */
+    pub fn crash(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.unsynced.clear();
+        state.deleted.clear();
+        state.faults.clear();
+        state.fail_delete = false;
+        for tablespace in self.tablespaces.lock().unwrap().values() {
+            tablespace.crash();
+        }
+    }
+
+    // number of writes and appends seen so far
+    pub fn writes(&self) -> u64 {
+        self.state.lock().unwrap().writes
+    }
+
+    pub fn inner(&self) -> Arc<dyn StorageBackend> {
+        self.inner.clone()
+    }
+
+    // returns the current contents of a file, taking a copy of the durable one on first use
+    fn contents<'a>(
+        &self,
+        state: &'a mut FaultState,
+        filename: &str,
+    ) -> Result<&'a mut Vec<u8>, Error> {
+        if !state.unsynced.contains_key(filename) {
+            let mut image = Vec::new();
+            if !state.deleted.remove(filename) {
+                image = vec![0; self.inner.length(filename)? as usize];
+                if !image.is_empty() {
+                    self.inner.read_block(filename, 0, &mut image)?;
+                }
+            }
+            state.unsynced.insert(filename.to_string(), image);
+        }
+        Ok(state.unsynced.get_mut(filename).unwrap())
+    }
+}
+
+impl StorageBackend for FaultyBackend {
+    fn read_block(&self, filename: &str, blk_num: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        self.read_at(filename, blk_num * buf.len() as u64, buf)
+    }
+
+    fn write_block(&self, filename: &str, blk_num: u64, buf: &[u8]) -> Result<(), Error> {
+        self.write_at(filename, blk_num * buf.len() as u64, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let state = self.state.lock().unwrap();
+        if state.deleted.contains(filename) {
+            return Ok(0);
+        }
+        match state.unsynced.get(filename) {
+            Some(file) => {
+                let offset = (offset as usize).min(file.len());
+                let read_len = (file.len() - offset).min(buf.len());
+                buf[..read_len].copy_from_slice(&file[offset..offset + read_len]);
+                Ok(read_len)
+            }
+            None => self.inner.read_at(filename, offset, buf),
+        }
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        let offset = offset as usize;
+        let mut state = self.state.lock().unwrap();
+        state.writes += 1;
+        let nth = state.writes;
+        let len = match state.faults.remove(&nth) {
+            Some(Fault::Fail) => {
+                return Err(Error::other(format!(
+                    "injected failure of write {} to {}",
+                    nth, filename
+                )))
+            }
+            Some(Fault::Tear(len)) => len.min(buf.len()),
+            Some(Fault::Delay(delay)) => {
+                drop(state);
+                thread::sleep(delay);
+                state = self.state.lock().unwrap();
+                buf.len()
+            }
+            None => buf.len(),
+        };
+        let file = self.contents(&mut state, filename)?;
+        if file.len() < offset + len {
+            file.resize(offset + len, 0);
+        }
+        file[offset..offset + len].copy_from_slice(&buf[..len]);
+        Ok(())
+    }
+
+    fn append(&self, filename: &str, buf: &[u8]) -> Result<u64, Error> {
+        let blk_num = self.length(filename)? / buf.len() as u64;
+        self.write_at(filename, blk_num * buf.len() as u64, buf)?;
+        Ok(blk_num)
+    }
+
+    fn length(&self, filename: &str) -> Result<u64, Error> {
+        let state = self.state.lock().unwrap();
+        if state.deleted.contains(filename) {
+            return Ok(0);
+        }
+        match state.unsynced.get(filename) {
+            Some(file) => Ok(file.len() as u64),
+            None => self.inner.length(filename),
+        }
+    }
+
+    fn sync(&self, filename: &str, mode: SyncMode) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.deleted.remove(filename) {
+            self.inner.delete(filename)?;
+        }
+        if let Some(file) = state.unsynced.remove(filename) {
+            self.inner.delete(filename)?;
+            if !file.is_empty() {
+                self.inner.write_block(filename, 0, &file)?;
+            }
+            self.inner.sync(filename, mode)?;
+        }
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> Result<(), Error> {
+        self.inner.sync_directory()
+    }
+
+    fn close(&self, filename: &str) -> Result<(), Error> {
+        self.inner.close(filename)
+    }
+
+    fn truncate(&self, filename: &str, len: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        self.contents(&mut state, filename)?.truncate(len as usize);
+        Ok(())
+    }
+
+    fn delete(&self, filename: &str) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.fail_delete {
+            state.fail_delete = false;
+            return Err(Error::other(format!(
+                "injected failure of delete of {}",
+                filename
+            )));
+        }
+        state.unsynced.remove(filename);
+        state.deleted.insert(filename.to_string());
+        Ok(())
+    }
+
+    // the rename reaches the wrapped backend right away, unsynced writes to the file stay unsynced
+    fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.deleted.remove(from) {
+            self.inner.delete(from)?;
+        }
+        state.deleted.remove(to);
+        self.inner.rename(from, to)?;
+        match state.unsynced.remove(from) {
+            Some(file) => state.unsynced.insert(to.to_string(), file),
+            None => state.unsynced.remove(to),
+        };
+        Ok(())
+    }
+
+    fn files(&self) -> Result<Vec<String>, Error> {
+        let state = self.state.lock().unwrap();
+        let mut files: HashSet<String> = self.inner.files()?.into_iter().collect();
+        files.extend(state.unsynced.keys().cloned());
+        files.retain(|f| !state.deleted.contains(f));
+        Ok(files.into_iter().collect())
+    }
+
+    fn open_tablespace(&self, location: &str) -> Result<Arc<dyn StorageBackend>, Error> {
+        let mut tablespaces = self.tablespaces.lock().unwrap();
+        if !tablespaces.contains_key(location) {
+            let inner = self.inner.open_tablespace(location)?;
+            tablespaces.insert(location.to_string(), Arc::new(FaultyBackend::new(inner)));
+        }
+        Ok(tablespaces[location].clone())
+    }
+
+    fn identity(&self) -> Result<String, Error> {
+        self.inner.identity()
+    }
+}
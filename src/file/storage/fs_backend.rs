@@ -0,0 +1,628 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{Error, ErrorKind};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+#[cfg(unix)]
+use super::mmap::Mmap;
+use super::{StorageBackend, SyncMode};
+use crate::file::options::FileOptions;
+use crate::file::superblock::CONTROL_FILE;
+
+// Keeps every file as a regular file in the database directory.
+// Blocks are read and written with positional I/O, so threads working on the same file
+// do not have to take turns seeking; only appends to a file are serialized.
+// At most max_open_files handles are cached, the least recently used one is closed first.
+// With mmap_reads, reads are copied out of a shared mapping of the file instead.
+//
+// With a segment_size, every file but the control file is split into segment files
+// named <file>.0, <file>.1, ... of segment_size bytes each, so that a table is not limited
+// by the largest file the filesystem allows and old segments can be moved on their own.
+//
+// With read_only, files are opened for reading only and never created; a missing file reads
+// as empty and anything that would change a file fails with PermissionDenied.
+pub struct FsBackend {
+    db_directory: PathBuf,
+    max_open_files: usize,
+    mmap_reads: bool,
+    // 0 if files are not split
+    segment_size: u64,
+    read_only: bool,
+    // segments written since their file was last synced
+    dirty_segments: Mutex<HashSet<String>>,
+    // segments whose directory entry has been synced
+    synced_segments: Mutex<HashSet<String>>,
+    // held shared while reading from a mapping and exclusively while a file shrinks,
+    // as touching a mapping past the end of its file kills the process
+    resize_lock: RwLock<()>,
+    open_files: Mutex<OpenFiles>,
+}
+
+#[derive(Default)]
+struct OpenFiles {
+    files: HashMap<PathBuf, (Arc<OpenFile>, u64)>,
+    // incremented on every use, the handle with the smallest value is the least recently used
+    clock: u64,
+}
+
+struct OpenFile {
+    file: fs::File,
+    // held while the end of the file is computed and written to
+    append_lock: Mutex<()>,
+    // mapping of the file for mmap_reads, replaced by a longer one as the file grows
+    #[cfg(unix)]
+    mapping: RwLock<Option<Mmap>>,
+}
+
+impl FsBackend {
+    pub fn new(db_directory: PathBuf) -> Result<Self, Error> {
+        Self::with_options(db_directory, &FileOptions::default())
+    }
+
+    pub fn with_options(db_directory: PathBuf, options: &FileOptions) -> Result<Self, Error> {
+        if !db_directory.exists() {
+            if options.read_only {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} does not exist", db_directory.display()),
+                ));
+            }
+            fs::create_dir_all(&db_directory)?;
+        }
+        Ok(Self {
+            db_directory,
+            max_open_files: options.max_open_files.max(1),
+            mmap_reads: options.mmap_reads,
+            segment_size: options.segment_size,
+            read_only: options.read_only,
+            dirty_segments: Mutex::new(HashSet::new()),
+            synced_segments: Mutex::new(HashSet::new()),
+            resize_lock: RwLock::new(()),
+            open_files: Mutex::new(OpenFiles::default()),
+        })
+    }
+
+    // number of file handles currently cached
+    pub fn open_file_count(&self) -> usize {
+        self.open_files.lock().unwrap().files.len()
+    }
+
+    fn get_file(&self, filename: &str) -> Result<Arc<OpenFile>, Error> {
+        self.open_file(filename, !self.read_only)
+    }
+
+    // returns the cached handle of the file or opens it, creating a missing file if asked to
+    fn open_file(&self, filename: &str, create: bool) -> Result<Arc<OpenFile>, Error> {
+        let path = self.db_directory.join(filename);
+        let mut open_files = self.open_files.lock().unwrap();
+        open_files.clock += 1;
+        let now = open_files.clock;
+        if let Some((file, last_used)) = open_files.files.get_mut(&path) {
+            *last_used = now;
+            let file = file.clone();
+            open_files.evict_down_to(self.max_open_files);
+            return Ok(file);
+        }
+        open_files.evict_down_to(self.max_open_files - 1);
+        let arc_file = Arc::new(OpenFile {
+            file: OpenOptions::new()
+                .read(true)
+                .write(!self.read_only)
+                .create(create)
+                .truncate(false)
+                .open(path.clone())?,
+            append_lock: Mutex::new(()),
+            #[cfg(unix)]
+            mapping: RwLock::new(None),
+        });
+        open_files.files.insert(path, (arc_file.clone(), now));
+        Ok(arc_file)
+    }
+
+    /**
+     * returns the paths of the segments of a file in order, e.g. for archiving them.
+     * a file that is not segmented has no segments.
+     */
+    pub fn segment_paths(&self, filename: &str) -> Result<Vec<PathBuf>, Error> {
+        if !self.is_segmented(filename) {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .segments(filename)?
+            .into_iter()
+            .map(|(_, segment)| self.db_directory.join(segment))
+            .collect())
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is opened read-only", self.db_directory.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn is_segmented(&self, filename: &str) -> bool {
+        self.segment_size > 0 && filename != CONTROL_FILE
+    }
+
+    // name of the file holding segment n, the file itself if it is not segmented
+    fn piece_name(&self, filename: &str, n: u64) -> String {
+        if self.is_segmented(filename) {
+            segment_name(filename, n)
+        } else {
+            filename.to_string()
+        }
+    }
+
+    /**
+     * splits the byte range [offset, offset + len) of a file into the ranges of the segments
+     * it covers: (segment file, offset in the segment, range in the buffer).
+     */
+    fn pieces(&self, filename: &str, offset: u64, len: usize) -> Vec<(String, u64, Range<usize>)> {
+        if !self.is_segmented(filename) {
+            return vec![(filename.to_string(), offset, 0..len)];
+        }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < len {
+            let pos = offset + start as u64;
+            let n = pos / self.segment_size;
+            let segment_offset = pos % self.segment_size;
+            let end = len.min(start + (self.segment_size - segment_offset) as usize);
+            pieces.push((segment_name(filename, n), segment_offset, start..end));
+            start = end;
+        }
+        pieces
+    }
+
+    fn read_pieces(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut read_len = 0;
+        for (name, piece_offset, range) in self.pieces(filename, offset, buf.len()) {
+            let file_io = match self.open_file(&name, false) {
+                // reads do not create files, a missing one is empty
+                Err(e) if e.kind() == ErrorKind::NotFound => break,
+                file_io => file_io?,
+            };
+            let len = range.len();
+            let n = self.read_file_at(&file_io, &mut buf[range], piece_offset)?;
+            read_len += n;
+            if n < len {
+                break;
+            }
+        }
+        Ok(read_len)
+    }
+
+    fn write_pieces(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        self.check_writable()?;
+        for (name, piece_offset, range) in self.pieces(filename, offset, buf.len()) {
+            let file_io = self.get_file(&name)?;
+            write_all_at(&file_io.file, &buf[range], piece_offset)?;
+            if self.is_segmented(filename) {
+                self.dirty_segments.lock().unwrap().insert(name);
+            }
+        }
+        Ok(())
+    }
+
+    fn truncate_file(&self, name: &str, len: u64) -> Result<(), Error> {
+        self.check_writable()?;
+        let file_io = self.get_file(name)?;
+        let _resize = self.resize_lock.write().unwrap();
+        let _append = file_io.append_lock.lock().unwrap();
+        // the mapping may reach past the new end, it is mapped again on the next read
+        #[cfg(unix)]
+        file_io.mapping.write().unwrap().take();
+        file_io.file.set_len(len)
+    }
+
+    fn remove_file(&self, name: &str) -> Result<(), Error> {
+        self.check_writable()?;
+        let path = self.db_directory.join(name);
+        let cached = self.open_files.lock().unwrap().files.remove(&path);
+        // an append still running on the handle finishes before the file goes away
+        let _append = cached
+            .as_ref()
+            .map(|(file_io, _)| file_io.append_lock.lock().unwrap());
+        self.dirty_segments.lock().unwrap().remove(name);
+        self.synced_segments.lock().unwrap().remove(name);
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn rename_file(&self, from: &str, to: &str) -> Result<(), Error> {
+        self.check_writable()?;
+        let from_path = self.db_directory.join(from);
+        let to_path = self.db_directory.join(to);
+        // cached handles would keep pointing at the files under their old names
+        let cached: Vec<_> = {
+            let mut open_files = self.open_files.lock().unwrap();
+            [&from_path, &to_path]
+                .into_iter()
+                .filter_map(|path| open_files.files.remove(path))
+                .collect()
+        };
+        let _append: Vec<_> = cached
+            .iter()
+            .map(|(file_io, _)| file_io.append_lock.lock().unwrap())
+            .collect();
+        let mut dirty_segments = self.dirty_segments.lock().unwrap();
+        if dirty_segments.remove(from) {
+            dirty_segments.insert(to.to_string());
+        }
+        self.synced_segments.lock().unwrap().remove(to);
+        fs::rename(from_path, to_path)
+    }
+
+    // existing segments of a file with their numbers, in order
+    fn segments(&self, filename: &str) -> Result<Vec<(u64, String)>, Error> {
+        let mut segments: Vec<(u64, String)> = self
+            .dir_entries()?
+            .into_iter()
+            .filter(|name| segment_base(name) == Some(filename))
+            .map(|name| (segment_number(&name).unwrap(), name))
+            .collect();
+        segments.sort();
+        Ok(segments)
+    }
+
+    fn dir_entries(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.db_directory)? {
+            let entry = entry?;
+            if entry.path().is_file() {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    // reads at the offset, from the mapping of the file if mmap_reads is set
+    fn read_file_at(
+        &self,
+        file_io: &OpenFile,
+        buf: &mut [u8],
+        offset: u64,
+    ) -> Result<usize, Error> {
+        #[cfg(unix)]
+        if self.mmap_reads {
+            return self.read_mapped(file_io, buf, offset);
+        }
+        read_full_at(&file_io.file, buf, offset)
+    }
+
+    /**
+     * copies from the mapping of the file. if the read goes past the end of the mapping,
+     * because the file grew since it was mapped, the file is mapped again at its current length.
+     */
+    #[cfg(unix)]
+    fn read_mapped(&self, file_io: &OpenFile, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+        let _resize = self.resize_lock.read().unwrap();
+        let end = offset as usize + buf.len();
+        {
+            let mapping = file_io.mapping.read().unwrap();
+            if let Some(m) = mapping.as_ref().filter(|m| m.as_slice().len() >= end) {
+                buf.copy_from_slice(&m.as_slice()[offset as usize..end]);
+                return Ok(buf.len());
+            }
+        }
+        let mut mapping = file_io.mapping.write().unwrap();
+        let file_len = file_io.file.metadata()?.len() as usize;
+        let mapped_len = mapping.as_ref().map_or(0, |m| m.as_slice().len());
+        if file_len > mapped_len {
+            *mapping = Some(Mmap::map(&file_io.file, file_len)?);
+        }
+        let data = mapping.as_ref().map_or(&[][..], |m| m.as_slice());
+        let start = (offset as usize).min(data.len());
+        let read_len = (data.len() - start).min(buf.len());
+        buf[..read_len].copy_from_slice(&data[start..start + read_len]);
+        Ok(read_len)
+    }
+}
+
+impl OpenFiles {
+    /**
+     * closes the least recently used handles no other thread is using until at most max are left.
+     * a handle in use stays open so that two handles never append to the same file at once,
+     * which means the cache can briefly hold more than max_open_files handles.
+     */
+    fn evict_down_to(&mut self, max: usize) {
+        while self.files.len() > max {
+            let lru = self
+                .files
+                .iter()
+                .filter(|(_, (file, _))| Arc::strong_count(file) == 1)
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(path, _)| path.clone());
+            match lru {
+                Some(path) => self.files.remove(&path),
+                None => return,
+            };
+        }
+    }
+}
+
+impl StorageBackend for FsBackend {
+    fn read_block(&self, filename: &str, blk_num: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        self.read_pieces(filename, blk_num * buf.len() as u64, buf)
+    }
+
+    fn write_block(&self, filename: &str, blk_num: u64, buf: &[u8]) -> Result<(), Error> {
+        self.write_pieces(filename, blk_num * buf.len() as u64, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        self.read_pieces(filename, offset, buf)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        self.write_pieces(filename, offset, buf)
+    }
+
+    fn read_blocks(
+        &self,
+        filename: &str,
+        blk_num: u64,
+        block_len: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.read_pieces(filename, blk_num * block_len as u64, buf)
+    }
+
+    fn write_blocks(
+        &self,
+        filename: &str,
+        blk_num: u64,
+        block_len: usize,
+        buf: &[u8],
+    ) -> Result<(), Error> {
+        self.write_pieces(filename, blk_num * block_len as u64, buf)
+    }
+
+    fn append(&self, filename: &str, buf: &[u8]) -> Result<u64, Error> {
+        self.check_writable()?;
+        // appends to a segmented file are serialized on its first segment
+        let file_io = self.get_file(&self.piece_name(filename, 0))?;
+        let _append = file_io.append_lock.lock().unwrap();
+        let blk_num = self.length(filename)? / buf.len() as u64;
+        self.write_pieces(filename, blk_num * buf.len() as u64, buf)?;
+        Ok(blk_num)
+    }
+
+    fn length(&self, filename: &str) -> Result<u64, Error> {
+        if !self.is_segmented(filename) {
+            return file_length(&self.db_directory.join(filename));
+        }
+        // the highest segment decides, a write past the end may have left out the ones before it
+        match self.segments(filename)?.pop() {
+            Some((last, segment)) => {
+                let last_len = file_length(&self.db_directory.join(segment))?;
+                Ok(last * self.segment_size + last_len)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn sync(&self, filename: &str, mode: SyncMode) -> Result<(), Error> {
+        // nothing was written
+        if self.read_only {
+            return Ok(());
+        }
+        if !self.is_segmented(filename) {
+            return sync_file(&self.get_file(filename)?.file, mode);
+        }
+        let dirty: Vec<String> = {
+            let mut dirty_segments = self.dirty_segments.lock().unwrap();
+            let dirty = dirty_segments
+                .iter()
+                .filter(|segment| segment_base(segment) == Some(filename))
+                .cloned()
+                .collect();
+            dirty_segments.retain(|segment| segment_base(segment) != Some(filename));
+            dirty
+        };
+        let mut created = false;
+        for segment in dirty {
+            sync_file(&self.get_file(&segment)?.file, mode)?;
+            created |= self.synced_segments.lock().unwrap().insert(segment);
+        }
+        // a segment created since the last sync only survives a crash once its directory entry does
+        if created {
+            self.sync_directory()?;
+        }
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> Result<(), Error> {
+        fs::File::open(&self.db_directory)?.sync_all()
+    }
+
+    fn truncate(&self, filename: &str, len: u64) -> Result<(), Error> {
+        if !self.is_segmented(filename) {
+            return self.truncate_file(filename, len);
+        }
+        let keep = len.div_ceil(self.segment_size);
+        let _resize = self.resize_lock.write().unwrap();
+        for (n, segment) in self.segments(filename)? {
+            if n >= keep {
+                self.remove_file(&segment)?;
+            }
+        }
+        drop(_resize);
+        if keep > 0 {
+            let last = keep - 1;
+            self.truncate_file(
+                &segment_name(filename, last),
+                len - last * self.segment_size,
+            )?;
+        }
+        Ok(())
+    }
+
+    // handles another thread is using stay open, like on eviction, and are closed later on
+    fn close(&self, filename: &str) -> Result<(), Error> {
+        let path = self.db_directory.join(filename);
+        let mut open_files = self.open_files.lock().unwrap();
+        open_files.files.retain(|p, (file_io, _)| {
+            let is_file = *p == path
+                || self.is_segmented(filename)
+                    && p.file_name()
+                        .and_then(|name| name.to_str())
+                        .and_then(segment_base)
+                        == Some(filename);
+            !is_file || Arc::strong_count(file_io) > 1
+        });
+        Ok(())
+    }
+
+    fn delete(&self, filename: &str) -> Result<(), Error> {
+        if !self.is_segmented(filename) {
+            return self.remove_file(filename);
+        }
+        for (_, segment) in self.segments(filename)? {
+            self.remove_file(&segment)?;
+        }
+        Ok(())
+    }
+
+    // a segmented file is renamed segment by segment, so only one that fits a segment is replaced in one step
+    fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        if !self.is_segmented(from) || !self.is_segmented(to) {
+            return self.rename_file(from, to);
+        }
+        let _resize = self.resize_lock.write().unwrap();
+        let mut end = 0;
+        for (n, segment) in self.segments(from)? {
+            self.rename_file(&segment, &segment_name(to, n))?;
+            end = n + 1;
+        }
+        for (n, segment) in self.segments(to)? {
+            if n >= end {
+                self.remove_file(&segment)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn files(&self) -> Result<Vec<String>, Error> {
+        let mut files = Vec::new();
+        for name in self.dir_entries()? {
+            let name = match segment_base(&name) {
+                Some(base) if self.is_segmented(base) => base.to_string(),
+                _ => name,
+            };
+            if !files.contains(&name) {
+                files.push(name);
+            }
+        }
+        Ok(files)
+    }
+
+    fn open_tablespace(&self, location: &str) -> Result<Arc<dyn StorageBackend>, Error> {
+        let options = FileOptions {
+            max_open_files: self.max_open_files,
+            mmap_reads: self.mmap_reads,
+            segment_size: self.segment_size,
+            read_only: self.read_only,
+            ..FileOptions::default()
+        };
+        Ok(Arc::new(Self::with_options(
+            self.db_directory.join(location),
+            &options,
+        )?))
+    }
+
+    fn identity(&self) -> Result<String, Error> {
+        Ok(fs::canonicalize(&self.db_directory)?.display().to_string())
+    }
+}
+
+fn segment_name(filename: &str, n: u64) -> String {
+    format!("{}.{}", filename, n)
+}
+
+fn segment_number(name: &str) -> Option<u64> {
+    let (_, n) = name.rsplit_once('.')?;
+    // leading zeros would give two names for the same segment
+    if n.is_empty() || (n.len() > 1 && n.starts_with('0')) {
+        return None;
+    }
+    n.parse().ok()
+}
+
+// the file a segment belongs to, None if the name is not that of a segment
+fn segment_base(name: &str) -> Option<&str> {
+    segment_number(name)?;
+    name.rsplit_once('.').map(|(base, _)| base)
+}
+
+fn file_length(path: &Path) -> Result<u64, Error> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+fn sync_file(file: &fs::File, mode: SyncMode) -> Result<(), Error> {
+    match mode {
+        SyncMode::Data => file.sync_data(),
+        SyncMode::Full => file.sync_all(),
+    }
+}
+
+// reads until the buffer is full or the end of the file is reached, returning the number of bytes read
+fn read_full_at(file: &fs::File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    let mut read_len = 0;
+    while read_len < buf.len() {
+        match read_at(file, &mut buf[read_len..], offset + read_len as u64) {
+            Ok(0) => break,
+            Ok(n) => read_len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read_len)
+}
+
+fn write_all_at(file: &fs::File, buf: &[u8], offset: u64) -> Result<(), Error> {
+    let mut written = 0;
+    while written < buf.len() {
+        match write_at(file, &buf[written..], offset + written as u64) {
+            Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_at(file: &fs::File, buf: &[u8], offset: u64) -> Result<usize, Error> {
+    std::os::unix::fs::FileExt::write_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &fs::File, buf: &[u8], offset: u64) -> Result<usize, Error> {
+    std::os::windows::fs::FileExt::seek_write(file, buf, offset)
+}
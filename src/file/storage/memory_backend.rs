@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+
+use super::{StorageBackend, SyncMode};
+
+// Keeps every file in memory. Clones share the same files, so a database can be
+// closed and reopened on the same MemoryBackend.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    // backends of the tablespaces opened from this one, by location
+    tablespaces: Arc<Mutex<HashMap<String, MemoryBackend>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn read_block(&self, filename: &str, blk_num: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        self.read_at(filename, blk_num * buf.len() as u64, buf)
+    }
+
+    fn write_block(&self, filename: &str, blk_num: u64, buf: &[u8]) -> Result<(), Error> {
+        self.write_at(filename, blk_num * buf.len() as u64, buf)
+    }
+
+    fn read_at(&self, filename: &str, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let files = self.files.lock().unwrap();
+        let file = match files.get(filename) {
+            Some(file) => file,
+            None => return Ok(0),
+        };
+        let offset = (offset as usize).min(file.len());
+        let read_len = (file.len() - offset).min(buf.len());
+        buf[..read_len].copy_from_slice(&file[offset..offset + read_len]);
+        Ok(read_len)
+    }
+
+    fn write_at(&self, filename: &str, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.entry(filename.to_string()).or_default();
+        let offset = offset as usize;
+        if file.len() < offset + buf.len() {
+            file.resize(offset + buf.len(), 0);
+        }
+        file[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn append(&self, filename: &str, buf: &[u8]) -> Result<u64, Error> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.entry(filename.to_string()).or_default();
+        let blk_num = file.len() / buf.len();
+        file.truncate(blk_num * buf.len());
+        file.extend_from_slice(buf);
+        Ok(blk_num as u64)
+    }
+
+    fn length(&self, filename: &str) -> Result<u64, Error> {
+        let files = self.files.lock().unwrap();
+        Ok(files.get(filename).map_or(0, |file| file.len() as u64))
+    }
+
+    fn sync(&self, _filename: &str, _mode: SyncMode) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn truncate(&self, filename: &str, len: u64) -> Result<(), Error> {
+        let mut files = self.files.lock().unwrap();
+        files
+            .entry(filename.to_string())
+            .or_default()
+            .truncate(len as usize);
+        Ok(())
+    }
+
+    fn delete(&self, filename: &str) -> Result<(), Error> {
+        self.files.lock().unwrap().remove(filename);
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.remove(from).unwrap_or_default();
+        files.insert(to.to_string(), file);
+        Ok(())
+    }
+
+    fn files(&self) -> Result<Vec<String>, Error> {
+        Ok(self.files.lock().unwrap().keys().cloned().collect())
+    }
+
+    fn open_tablespace(&self, location: &str) -> Result<Arc<dyn StorageBackend>, Error> {
+        let mut tablespaces = self.tablespaces.lock().unwrap();
+        let backend = tablespaces.entry(location.to_string()).or_default();
+        Ok(Arc::new(backend.clone()))
+    }
+
+    // clones share their files, so they have the same identity
+    fn identity(&self) -> Result<String, Error> {
+        Ok(format!("memory:{:p}", Arc::as_ptr(&self.files)))
+    }
+}
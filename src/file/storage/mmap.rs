@@ -0,0 +1,67 @@
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::Error;
+use std::os::fd::AsRawFd;
+use std::{ptr, slice};
+
+// the values are the same on linux and the bsds, including macos
+const PROT_READ: i32 = 1;
+const MAP_SHARED: i32 = 1;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+// A read-only shared mapping of the first len bytes of a file.
+// Writes made to the file through its descriptor show up in the mapping, as both go through
+// the page cache. The file must not shrink below len while the mapping is read from, or the
+// reader is killed with SIGBUS; FsBackend takes care of that.
+pub struct Mmap {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+// the mapping is read-only, so sharing it between threads is safe
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    pub fn map(file: &File, len: usize) -> Result<Self, Error> {
+        assert!(len > 0, "cannot map an empty file");
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ,
+                MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        // MAP_FAILED
+        if ptr as isize == -1 {
+            return Err(Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
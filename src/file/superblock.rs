@@ -0,0 +1,391 @@
+use std::{
+    error, fmt,
+    io::{self, Error},
+    mem::size_of,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::file::{
+    checksum::crc32,
+    encryption::KEY_CHECK_SIZE,
+    options::FileOptions,
+    storage::{StorageBackend, SyncMode},
+};
+
+// The control file describes the on-disk format of a database directory.
+// It is written once when the database is created and validated every time it is opened.
+// | checksum (u32) | magic (8 bytes) | format version (u32) | block size (u32) | created at (u64) |
+// | creator version length (u32) | creator version (bytes) | ... | log segment blocks (u64) |
+// | nonce limit (u64) | segment size (u64) | features (u32) | key check (16 bytes) |
+// The key check sits at the end of the block and is all zeros for a database that is not encrypted.
+// The nonce limit is the only field that changes after the database is created, see
+// Superblock::store_nonce_limit.
+pub const CONTROL_FILE: &str = "simpledb.control";
+pub const MAGIC: &[u8; 8] = b"SIMPLEDB";
+pub const FORMAT_VERSION: u32 = 1;
+const SUPERBLOCK_SIZE: usize = 128;
+
+const CHECKSUM_POS: usize = 0;
+const MAGIC_POS: usize = CHECKSUM_POS + size_of::<u32>();
+const VERSION_POS: usize = MAGIC_POS + MAGIC.len();
+const BLOCK_SIZE_POS: usize = VERSION_POS + size_of::<u32>();
+const CREATED_AT_POS: usize = BLOCK_SIZE_POS + size_of::<u32>();
+const CREATOR_POS: usize = CREATED_AT_POS + size_of::<u64>();
+const KEY_CHECK_POS: usize = SUPERBLOCK_SIZE - KEY_CHECK_SIZE;
+const FEATURES_POS: usize = KEY_CHECK_POS - size_of::<u32>();
+const SEGMENT_SIZE_POS: usize = FEATURES_POS - size_of::<u64>();
+const NONCE_LIMIT_POS: usize = SEGMENT_SIZE_POS - size_of::<u64>();
+const LOG_SEGMENT_BLOCKS_POS: usize = NONCE_LIMIT_POS - size_of::<u64>();
+
+// set in the features of a database whose blocks are stored compressed, see BlockMap
+pub const FEATURE_COMPRESSION: u32 = 1;
+
+// Upgrade steps from one format version to the next, UPGRADES[0] upgrades version 1 to 2 and so on.
+// Each step rewrites whatever files changed layout; the superblock is rewritten afterwards.
+// There is only version 1 so far.
+type Upgrade = fn(&Superblock, &dyn StorageBackend) -> Result<(), Error>;
+const UPGRADES: &[Upgrade] = &[];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Superblock {
+    format_version: u32,
+    block_size: usize,
+    // seconds since the unix epoch
+    created_at: u64,
+    // version of simple_db_rust that created the database
+    created_by: String,
+    // FEATURE_* flags fixed when the database was created
+    features: u32,
+    // size of the segment files the filesystem backend splits files into, 0 if it does not
+    segment_size: u64,
+    // blocks per log segment file, 0 if the log is a single file
+    log_segment_blocks: u64,
+    // set when the database is encrypted, see EncryptionKey::key_check
+    key_check: Option<[u8; KEY_CHECK_SIZE]>,
+    // nonces below this one may have been used to encrypt a block, 0 if the database is not encrypted
+    nonce_limit: u64,
+}
+
+impl Superblock {
+    pub fn new(block_size: usize, options: &FileOptions) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            block_size,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            created_by: env!("CARGO_PKG_VERSION").to_string(),
+            features: if options.compression {
+                FEATURE_COMPRESSION
+            } else {
+                0
+            },
+            segment_size: options.segment_size,
+            log_segment_blocks: options.log_segment_blocks,
+            key_check: options
+                .encryption_key
+                .as_ref()
+                .map(|key| key.key_check(CONTROL_FILE)),
+            nonce_limit: 0,
+        }
+    }
+
+    /**
+     * opens the superblock of the database directory, creating it if the database is new.
+     * the stored block size has to match the requested one, the key has to be the one the
+     * database was created with, and older formats are upgraded.
+     * features such as compression are taken from the options only when the database is created.
+     */
+    pub fn open(
+        storage: &dyn StorageBackend,
+        block_size: usize,
+        options: &FileOptions,
+    ) -> Result<Self, Error> {
+        let mut superblock = match Self::read(storage)? {
+            Some(superblock) => superblock,
+            None => {
+                let superblock = Self::new(block_size, options);
+                superblock.write(storage)?;
+                return Ok(superblock);
+            }
+        };
+        if superblock.block_size != block_size {
+            return Err(SuperblockError::BlockSizeMismatch {
+                stored: superblock.block_size,
+                requested: block_size,
+            }
+            .into());
+        }
+        if superblock.segment_size != options.segment_size {
+            return Err(SuperblockError::SegmentSizeMismatch {
+                stored: superblock.segment_size,
+                requested: options.segment_size,
+            }
+            .into());
+        }
+        if superblock.log_segment_blocks != options.log_segment_blocks {
+            return Err(SuperblockError::LogSegmentSizeMismatch {
+                stored: superblock.log_segment_blocks,
+                requested: options.log_segment_blocks,
+            }
+            .into());
+        }
+        match (&superblock.key_check, &options.encryption_key) {
+            (None, None) => {}
+            (Some(_), None) => return Err(SuperblockError::KeyRequired.into()),
+            (None, Some(_)) => return Err(SuperblockError::NotEncrypted.into()),
+            (Some(check), Some(key)) => {
+                if *check != key.key_check(CONTROL_FILE) {
+                    return Err(SuperblockError::WrongKey.into());
+                }
+            }
+        }
+        // an older format is still read as it is, only upgrading it would write
+        if !options.read_only {
+            superblock.upgrade(storage)?;
+        }
+        Ok(superblock)
+    }
+
+    /**
+     * reads the superblock of the database directory without checking it against any options,
+     * e.g. to learn the block size of a database before opening it. None if there is no database.
+     */
+    pub fn read(storage: &dyn StorageBackend) -> Result<Option<Self>, Error> {
+        if storage.length(CONTROL_FILE)? == 0 {
+            return Ok(None);
+        }
+        let mut b = vec![0; SUPERBLOCK_SIZE];
+        let read_len = storage.read_block(CONTROL_FILE, 0, &mut b)?;
+        Ok(Some(Self::decode(&b[..read_len])?))
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn created_by(&self) -> &str {
+        &self.created_by
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key_check.is_some()
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.features & FEATURE_COMPRESSION != 0
+    }
+
+    pub fn segment_size(&self) -> u64 {
+        self.segment_size
+    }
+
+    pub fn log_segment_blocks(&self) -> u64 {
+        self.log_segment_blocks
+    }
+
+    // the nonce limit when the superblock was read, FileManager keeps track of it from then on
+    pub fn nonce_limit(&self) -> u64 {
+        self.nonce_limit
+    }
+
+    /**
+     * stores a new nonce limit, which has to be larger than the one stored before.
+     * the superblock is synced before this returns, so no nonce below the limit is handed
+     * out again after a crash.
+     */
+    pub fn store_nonce_limit(&self, storage: &dyn StorageBackend, limit: u64) -> Result<(), Error> {
+        let mut superblock = self.clone();
+        superblock.nonce_limit = limit;
+        superblock.write(storage)
+    }
+
+    fn upgrade(&mut self, storage: &dyn StorageBackend) -> Result<(), Error> {
+        let found = self.format_version;
+        while self.format_version < FORMAT_VERSION {
+            let step = UPGRADES[(self.format_version - 1) as usize];
+            step(self, storage)?;
+            self.format_version += 1;
+        }
+        if self.format_version != found {
+            self.write(storage)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, storage: &dyn StorageBackend) -> Result<(), Error> {
+        storage.write_block(CONTROL_FILE, 0, &self.encode())?;
+        storage.sync(CONTROL_FILE, SyncMode::Full)?;
+        storage.sync_directory()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut b = vec![0; SUPERBLOCK_SIZE];
+        let creator = self.created_by.as_bytes();
+        b[MAGIC_POS..VERSION_POS].copy_from_slice(MAGIC);
+        b[VERSION_POS..BLOCK_SIZE_POS].copy_from_slice(&self.format_version.to_le_bytes());
+        b[BLOCK_SIZE_POS..CREATED_AT_POS].copy_from_slice(&(self.block_size as u32).to_le_bytes());
+        b[CREATED_AT_POS..CREATOR_POS].copy_from_slice(&self.created_at.to_le_bytes());
+        let creator_pos = CREATOR_POS + size_of::<u32>();
+        b[CREATOR_POS..creator_pos].copy_from_slice(&(creator.len() as u32).to_le_bytes());
+        b[creator_pos..creator_pos + creator.len()].copy_from_slice(creator);
+        b[LOG_SEGMENT_BLOCKS_POS..NONCE_LIMIT_POS]
+            .copy_from_slice(&self.log_segment_blocks.to_le_bytes());
+        b[NONCE_LIMIT_POS..SEGMENT_SIZE_POS].copy_from_slice(&self.nonce_limit.to_le_bytes());
+        b[SEGMENT_SIZE_POS..FEATURES_POS].copy_from_slice(&self.segment_size.to_le_bytes());
+        b[FEATURES_POS..KEY_CHECK_POS].copy_from_slice(&self.features.to_le_bytes());
+        if let Some(check) = &self.key_check {
+            b[KEY_CHECK_POS..].copy_from_slice(check);
+        }
+        let checksum = crc32(&b[MAGIC_POS..]);
+        b[CHECKSUM_POS..MAGIC_POS].copy_from_slice(&checksum.to_le_bytes());
+        b
+    }
+
+    fn decode(b: &[u8]) -> Result<Self, SuperblockError> {
+        if b.len() != SUPERBLOCK_SIZE {
+            return Err(SuperblockError::Corrupted);
+        }
+        if &b[MAGIC_POS..VERSION_POS] != MAGIC {
+            return Err(SuperblockError::BadMagic);
+        }
+        if read_u32(b, CHECKSUM_POS) != crc32(&b[MAGIC_POS..]) {
+            return Err(SuperblockError::Corrupted);
+        }
+        let format_version = read_u32(b, VERSION_POS);
+        if format_version == 0 || format_version > FORMAT_VERSION {
+            return Err(SuperblockError::UnsupportedVersion(format_version));
+        }
+        let creator_pos = CREATOR_POS + size_of::<u32>();
+        let creator_len = read_u32(b, CREATOR_POS) as usize;
+        let created_by = b[..LOG_SEGMENT_BLOCKS_POS]
+            .get(creator_pos..creator_pos + creator_len)
+            .and_then(|s| String::from_utf8(s.to_vec()).ok())
+            .ok_or(SuperblockError::Corrupted)?;
+        Ok(Self {
+            format_version,
+            block_size: read_u32(b, BLOCK_SIZE_POS) as usize,
+            created_at: u64::from_le_bytes(b[CREATED_AT_POS..CREATOR_POS].try_into().unwrap()),
+            created_by,
+            features: read_u32(b, FEATURES_POS),
+            segment_size: u64::from_le_bytes(b[SEGMENT_SIZE_POS..FEATURES_POS].try_into().unwrap()),
+            log_segment_blocks: u64::from_le_bytes(
+                b[LOG_SEGMENT_BLOCKS_POS..NONCE_LIMIT_POS]
+                    .try_into()
+                    .unwrap(),
+            ),
+            key_check: Some(b[KEY_CHECK_POS..].try_into().unwrap())
+                .filter(|check: &[u8; KEY_CHECK_SIZE]| check.iter().any(|b| *b != 0)),
+            nonce_limit: u64::from_le_bytes(
+                b[NONCE_LIMIT_POS..SEGMENT_SIZE_POS].try_into().unwrap(),
+            ),
+        })
+    }
+}
+
+fn read_u32(b: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(b[pos..pos + size_of::<u32>()].try_into().unwrap())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SuperblockError {
+    BadMagic,
+    Corrupted,
+    UnsupportedVersion(u32),
+    BlockSizeMismatch { stored: usize, requested: usize },
+    SegmentSizeMismatch { stored: u64, requested: u64 },
+    LogSegmentSizeMismatch { stored: u64, requested: u64 },
+    // the database is encrypted but no key was given
+    KeyRequired,
+    // a key was given but the database is not encrypted
+    NotEncrypted,
+    WrongKey,
+}
+
+impl fmt::Display for SuperblockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SuperblockError::BadMagic => {
+                write!(f, "{} is not a simpledb control file", CONTROL_FILE)
+            }
+            SuperblockError::Corrupted => write!(f, "{} is corrupted", CONTROL_FILE),
+            SuperblockError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported on-disk format version {} (this build supports up to {})",
+                v, FORMAT_VERSION
+            ),
+            SuperblockError::BlockSizeMismatch { stored, requested } => write!(
+                f,
+                "database was created with block size {} but opened with {}",
+                stored, requested
+            ),
+            SuperblockError::SegmentSizeMismatch { stored, requested } => write!(
+                f,
+                "database was created with segment size {} but opened with {}",
+                stored, requested
+            ),
+            SuperblockError::LogSegmentSizeMismatch { stored, requested } => write!(
+                f,
+                "database was created with {} blocks per log segment but opened with {}",
+                stored, requested
+            ),
+            SuperblockError::KeyRequired => write!(f, "database is encrypted, a key is required"),
+            SuperblockError::NotEncrypted => {
+                write!(f, "database is not encrypted but a key was given")
+            }
+            SuperblockError::WrongKey => write!(f, "wrong encryption key for database"),
+        }
+    }
+}
+
+impl error::Error for SuperblockError {}
+
+impl From<SuperblockError> for io::Error {
+    fn from(err: SuperblockError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::encryption::EncryptionKey;
+
+    #[test]
+    fn test_encode_decode() {
+        let superblock = Superblock::new(400, &FileOptions::default());
+        let mut b = superblock.encode();
+        assert_eq!(Superblock::decode(&b).unwrap(), superblock);
+
+        let options = FileOptions {
+            compression: true,
+            encryption_key: Some(EncryptionKey::new([7; 32])),
+            segment_size: 1 << 30,
+            log_segment_blocks: 64,
+            ..FileOptions::default()
+        };
+        let mut featured = Superblock::new(400, &options);
+        featured.nonce_limit = 1 << 40;
+        assert!(featured.is_encrypted() && featured.is_compressed());
+        assert_eq!(Superblock::decode(&featured.encode()).unwrap(), featured);
+
+        b[BLOCK_SIZE_POS] ^= 1;
+        assert_eq!(Superblock::decode(&b), Err(SuperblockError::Corrupted));
+
+        let mut newer = superblock.clone();
+        newer.format_version = FORMAT_VERSION + 1;
+        assert_eq!(
+            Superblock::decode(&newer.encode()),
+            Err(SuperblockError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+    }
+}
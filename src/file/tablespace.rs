@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+use crate::file::storage::{StorageBackend, SyncMode};
+
+// The tablespaces of a database and the files placed in them are kept in the default
+// tablespace in a file with this name, one tab separated entry per line:
+// tablespace <name> <location>
+// file <filename> <tablespace>
+// Files without an entry live in the default tablespace, or in the temp tablespace if they are temp files.
+pub const TABLESPACE_FILE: &str = "simpledb.tablespaces";
+// the catalog is written to this file first and then renamed over the old one
+const NEW_TABLESPACE_FILE: &str = "simpledb.tablespaces.new";
+// the tablespace of the storage the database was opened on
+pub const DEFAULT_TABLESPACE: &str = "default";
+
+#[derive(Debug, Default, Clone)]
+pub struct TablespaceCatalog {
+    // name -> location
+    locations: BTreeMap<String, String>,
+    // filename -> tablespace
+    files: BTreeMap<String, String>,
+}
+
+impl TablespaceCatalog {
+    pub fn load(storage: &dyn StorageBackend) -> Result<Self, Error> {
+        let mut b = vec![0; storage.length(TABLESPACE_FILE)? as usize];
+        if !b.is_empty() {
+            storage.read_at(TABLESPACE_FILE, 0, &mut b)?;
+        }
+        let text = String::from_utf8(b).map_err(|_| corrupted("not utf-8"))?;
+        let mut catalog = Self::default();
+        for line in text.lines() {
+            match line.split('\t').collect::<Vec<_>>()[..] {
+                ["tablespace", name, location] => {
+                    catalog
+                        .locations
+                        .insert(name.to_string(), location.to_string());
+                }
+                ["file", filename, tablespace] => {
+                    catalog
+                        .files
+                        .insert(filename.to_string(), tablespace.to_string());
+                }
+                _ => return Err(corrupted(line)),
+            }
+        }
+        if let Some(tablespace) = catalog
+            .files
+            .values()
+            .find(|t| !catalog.locations.contains_key(*t))
+        {
+            return Err(corrupted(&format!("unknown tablespace {}", tablespace)));
+        }
+        Ok(catalog)
+    }
+
+    /**
+     * writes the catalog and syncs it, as losing it would lose track of where files are.
+     * it replaces the old catalog by a rename, so a crash leaves one or the other behind.
+     */
+    pub fn store(&self, storage: &dyn StorageBackend) -> Result<(), Error> {
+        let mut text = String::new();
+        for (name, location) in &self.locations {
+            text += &format!("tablespace\t{}\t{}\n", name, location);
+        }
+        for (filename, tablespace) in &self.files {
+            text += &format!("file\t{}\t{}\n", filename, tablespace);
+        }
+        storage.delete(NEW_TABLESPACE_FILE)?;
+        storage.write_at(NEW_TABLESPACE_FILE, 0, text.as_bytes())?;
+        storage.sync(NEW_TABLESPACE_FILE, SyncMode::Full)?;
+        storage.rename(NEW_TABLESPACE_FILE, TABLESPACE_FILE)?;
+        storage.sync_directory()
+    }
+
+    // (name, location) of every tablespace but the default one
+    pub fn tablespaces(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.locations
+            .iter()
+            .map(|(name, location)| (name.as_str(), location.as_str()))
+    }
+
+    pub fn location(&self, name: &str) -> Option<&str> {
+        self.locations.get(name).map(|l| l.as_str())
+    }
+
+    pub fn add(&mut self, name: &str, location: &str) -> Result<(), Error> {
+        if name == DEFAULT_TABLESPACE || !is_valid(name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid tablespace name {:?}", name),
+            ));
+        }
+        if !is_valid(location) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid tablespace location {:?}", location),
+            ));
+        }
+        self.locations
+            .insert(name.to_string(), location.to_string());
+        Ok(())
+    }
+
+    // the tablespace the file was placed in, None if it was not placed anywhere
+    pub fn tablespace_of(&self, filename: &str) -> Option<&str> {
+        self.files.get(filename).map(|t| t.as_str())
+    }
+
+    // records the tablespace of the file, the default one removes its entry
+    pub fn place(&mut self, filename: &str, tablespace: &str) -> Result<(), Error> {
+        if tablespace == DEFAULT_TABLESPACE {
+            self.files.remove(filename);
+            return Ok(());
+        }
+        if !is_valid(filename) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid file name {:?}", filename),
+            ));
+        }
+        self.files
+            .insert(filename.to_string(), tablespace.to_string());
+        Ok(())
+    }
+
+    // forgets the files matching the predicate, returns whether there were any
+    pub fn remove_files(&mut self, f: impl Fn(&str) -> bool) -> bool {
+        let len = self.files.len();
+        self.files.retain(|filename, _| !f(filename));
+        self.files.len() != len
+    }
+}
+
+// names and locations end up in tab separated lines
+fn is_valid(s: &str) -> bool {
+    !s.is_empty() && !s.contains(['\t', '\n', '\r'])
+}
+
+fn corrupted(what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("corrupted {}: {}", TABLESPACE_FILE, what),
+    )
+}
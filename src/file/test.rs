@@ -1,11 +1,33 @@
 #[cfg(test)]
 mod tests {
 
-    use std::fs;
+    use std::{
+        fs::{self, OpenOptions},
+        io::{self, Seek, SeekFrom, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
 
     use crate::{
         app::simple_db::SimpleDB,
-        file::{block_id::BlockId, page::Page},
+        file::{
+            block_header::CorruptBlockError,
+            block_id::BlockId,
+            encryption::EncryptionKey,
+            file_manager::{is_temp_file, FileManager, TEMP_PREFIX},
+            options::FileOptions,
+            page::Page,
+            stats::FileKind,
+            storage::{
+                faulty_backend::FaultyBackend, fs_backend::FsBackend,
+                memory_backend::MemoryBackend, StorageBackend,
+            },
+            superblock::{SuperblockError, CONTROL_FILE, FORMAT_VERSION},
+            tablespace::TABLESPACE_FILE,
+        },
     };
     #[test]
     fn test_file() {
@@ -33,4 +55,818 @@ mod tests {
 
         fs::remove_dir_all("test_file").unwrap();
     }
+
+    #[test]
+    fn test_corrupt_block() {
+        let db = SimpleDB::new("./test_corrupt_block/file", 400, 8).unwrap();
+        let fm = db.file_manager();
+        let block_id = BlockId::new("test_corrupt_block", 1);
+        let mut page = Page::new(fm.block_size());
+        page.set_string(0, "abcdefghijklm");
+        page.set_lsn(7);
+        fm.write(&block_id, &mut page).unwrap();
+
+        let mut read_page = Page::new(fm.block_size());
+        fm.read(&block_id, &mut read_page).unwrap();
+        assert_eq!(read_page.lsn(), 7);
+
+        // blocks past the end of the file are empty pages
+        fm.read(&BlockId::new("test_corrupt_block", 5), &mut read_page)
+            .unwrap();
+        assert_eq!(read_page.get_int(0), 0);
+        assert_eq!(read_page.lsn(), 0);
+
+        // flip a byte of the page contents
+        let path = "./test_corrupt_block/file/test_corrupt_block";
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        let offset = fm.physical_block_size() as u64 + fm.physical_block_size() as u64 - 1;
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[1]).unwrap();
+        let err = fm.read(&block_id, &mut read_page).unwrap_err();
+        match err.get_ref().unwrap().downcast_ref::<CorruptBlockError>() {
+            Some(CorruptBlockError::ChecksumMismatch { block, .. }) => assert_eq!(block, &block_id),
+            _ => panic!("expected a checksum mismatch, got {}", err),
+        }
+
+        // cut the block in the middle as if the write was torn
+        file.set_len(offset - 100).unwrap();
+        let err = fm.read(&block_id, &mut read_page).unwrap_err();
+        match err.get_ref().unwrap().downcast_ref::<CorruptBlockError>() {
+            Some(CorruptBlockError::TornWrite { block, .. }) => assert_eq!(block, &block_id),
+            _ => panic!("expected a torn write, got {}", err),
+        }
+
+        fs::remove_dir_all("test_corrupt_block").unwrap();
+    }
+
+    #[test]
+    fn test_superblock() {
+        let created_at = {
+            let db = SimpleDB::new("./test_superblock", 400, 8).unwrap();
+            let superblock = db.file_manager().superblock().clone();
+            assert_eq!(superblock.block_size(), 400);
+            assert_eq!(superblock.format_version(), FORMAT_VERSION);
+            superblock.created_at()
+        };
+
+        let db = SimpleDB::new("./test_superblock", 400, 8).unwrap();
+        assert_eq!(db.file_manager().superblock().created_at(), created_at);
+        drop(db);
+
+        let err = SimpleDB::new("./test_superblock", 800, 8).err().unwrap();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SuperblockError>(),
+            Some(&SuperblockError::BlockSizeMismatch {
+                stored: 400,
+                requested: 800
+            })
+        );
+
+        let path = format!("./test_superblock/{}", CONTROL_FILE);
+        let mut b = fs::read(&path).unwrap();
+        b[4] = b'X';
+        fs::write(&path, b).unwrap();
+        let err = SimpleDB::new("./test_superblock", 400, 8).err().unwrap();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SuperblockError>(),
+            Some(&SuperblockError::BadMagic)
+        );
+
+        fs::remove_dir_all("test_superblock").unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let data_blk = BlockId::new("data", 0);
+        let temp_file;
+        {
+            let db = SimpleDB::new("./test_reopen", 400, 8).unwrap();
+            let fm = db.file_manager();
+            assert!(fm.is_new());
+            temp_file = fm.temp_file();
+            assert!(is_temp_file(&temp_file));
+            assert_ne!(fm.temp_file(), temp_file);
+            let mut page = Page::new(fm.block_size());
+            page.set_string(0, "persisted");
+            fm.write(&data_blk, &mut page).unwrap();
+            fm.write(&BlockId::new("temperature", 0), &mut page)
+                .unwrap();
+            fm.write(&BlockId::new("temp", 0), &mut page).unwrap();
+            fm.write(&BlockId::new(&temp_file, 0), &mut page).unwrap();
+        }
+
+        let db = SimpleDB::new("./test_reopen", 400, 8).unwrap();
+        let fm = db.file_manager();
+        assert!(!fm.is_new());
+        assert_eq!(fm.length("data").unwrap(), 1);
+        let mut page = Page::new(fm.block_size());
+        fm.read(&data_blk, &mut page).unwrap();
+        assert_eq!(page.get_string(0).unwrap(), "persisted");
+
+        // temp tables do not survive a restart, tables whose name merely starts like one do
+        assert!(!std::path::Path::new("./test_reopen")
+            .join(&temp_file)
+            .exists());
+        assert_eq!(fm.length("temperature").unwrap(), 1);
+        assert_eq!(fm.length("temp").unwrap(), 1);
+
+        fs::remove_dir_all("test_reopen").unwrap();
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        let storage = Arc::new(MemoryBackend::new());
+        let block_id = BlockId::new("test_file", 2);
+        {
+            let db = SimpleDB::with_backend(storage.clone(), 400, 8).unwrap();
+            let fm = db.file_manager();
+            assert!(fm.is_new());
+            let mut page = Page::new(fm.block_size());
+            page.set_string(88, "abcdefghijklm");
+            fm.write(&block_id, &mut page).unwrap();
+            fm.write(&BlockId::new(".temp1", 0), &mut page).unwrap();
+            assert_eq!(fm.length("test_file").unwrap(), 3);
+            assert_eq!(fm.append("test_file").unwrap().number(), 3);
+        }
+
+        let db = SimpleDB::with_backend(storage, 400, 8).unwrap();
+        let fm = db.file_manager();
+        assert!(!fm.is_new());
+        assert_eq!(fm.length("test_file").unwrap(), 4);
+        assert_eq!(fm.length(".temp1").unwrap(), 0);
+        let mut page = Page::new(fm.block_size());
+        fm.read(&block_id, &mut page).unwrap();
+        assert_eq!(page.get_string(88).unwrap(), "abcdefghijklm");
+    }
+
+    #[test]
+    fn test_fault_injection() {
+        let faulty = Arc::new(FaultyBackend::new(Arc::new(MemoryBackend::new())));
+        let db = SimpleDB::with_backend(faulty.clone(), 400, 8).unwrap();
+        let fm = db.file_manager();
+        let block_id = BlockId::new("data", 0);
+        let mut page = Page::new(fm.block_size());
+        page.set_int(0, 1);
+        fm.write(&block_id, &mut page).unwrap();
+        fm.sync("data").unwrap();
+
+        // a failed write leaves the block untouched
+        faulty.fail_nth_write(1);
+        page.set_int(0, 2);
+        assert!(fm.write(&block_id, &mut page).is_err());
+        fm.read(&block_id, &mut page).unwrap();
+        assert_eq!(page.get_int(0), 1);
+
+        // writes that were not synced are lost in a crash
+        page.set_int(0, 3);
+        fm.write(&block_id, &mut page).unwrap();
+        fm.read(&block_id, &mut page).unwrap();
+        assert_eq!(page.get_int(0), 3);
+        faulty.crash();
+        fm.read(&block_id, &mut page).unwrap();
+        assert_eq!(page.get_int(0), 1);
+
+        // a torn write is detected on the next read
+        faulty.tear_nth_write(1, 100);
+        page.set_int(300, 4);
+        fm.write(&block_id, &mut page).unwrap();
+        let err = fm.read(&block_id, &mut page).unwrap_err();
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<CorruptBlockError>(),
+            Some(CorruptBlockError::ChecksumMismatch { .. })
+        ));
+
+        // so is a torn append
+        faulty.tear_nth_write(1, 100);
+        let appended = fm.append("data").unwrap();
+        let err = fm.read(&appended, &mut page).unwrap_err();
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<CorruptBlockError>(),
+            Some(CorruptBlockError::TornWrite { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_write_many() {
+        let db = SimpleDB::new("./test_read_write_many", 400, 8).unwrap();
+        let fm = db.file_manager();
+        // two runs in "scan", one block in "other", then a block past the end of "scan"
+        let blks = vec![
+            BlockId::new("scan", 0),
+            BlockId::new("scan", 1),
+            BlockId::new("scan", 2),
+            BlockId::new("other", 0),
+            BlockId::new("scan", 4),
+        ];
+        let pages: Vec<Page> = (0..blks.len())
+            .map(|i| {
+                let mut page = Page::new(fm.block_size());
+                page.set_int(0, i as u32 + 1);
+                page
+            })
+            .collect();
+        fm.write_many(&blks[..4], &pages[..4]).unwrap();
+        assert_eq!(fm.length("scan").unwrap(), 3);
+
+        let mut read_pages: Vec<Page> = (0..blks.len())
+            .map(|_| Page::new(fm.block_size()))
+            .collect();
+        fm.read_many(&blks, &mut read_pages).unwrap();
+        for (i, page) in read_pages[..4].iter().enumerate() {
+            assert_eq!(page.get_int(0), i as u32 + 1);
+        }
+        assert_eq!(read_pages[4].get_int(0), 0);
+
+        // readers of the same file do not wait for each other
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let fm = fm.clone();
+                let blks = blks.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        for (i, blk) in blks[..4].iter().enumerate() {
+                            let mut page = Page::new(fm.block_size());
+                            fm.read(blk, &mut page).unwrap();
+                            assert_eq!(page.get_int(0), i as u32 + 1);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        fs::remove_dir_all("test_read_write_many").unwrap();
+    }
+
+    #[test]
+    fn test_free_blocks() {
+        let storage = Arc::new(MemoryBackend::new());
+        {
+            let db = SimpleDB::with_backend(storage.clone(), 400, 8).unwrap();
+            let fm = db.file_manager();
+            for _ in 0..4 {
+                fm.append("table").unwrap();
+            }
+            let mut page = Page::new(fm.block_size());
+            page.set_int(0, 7);
+            fm.write(&BlockId::new("table", 1), &mut page).unwrap();
+
+            fm.free_block(&BlockId::new("table", 1)).unwrap();
+            fm.free_block(&BlockId::new("table", 3)).unwrap();
+            assert!(fm.free_block(&BlockId::new("table", 4)).is_err());
+            assert_eq!(fm.free_blocks("table").unwrap(), 2);
+
+            // the lowest free block is reused first and comes back empty
+            let blk = fm.append("table").unwrap();
+            assert_eq!(blk.number(), 1);
+            fm.read(&blk, &mut page).unwrap();
+            assert_eq!(page.get_int(0), 0);
+        }
+
+        // the free list survives a reopen
+        let db = SimpleDB::with_backend(storage, 400, 8).unwrap();
+        let fm = db.file_manager();
+        assert_eq!(fm.free_blocks("table").unwrap(), 1);
+        assert_eq!(fm.length("table").unwrap(), 4);
+
+        fm.free_block(&BlockId::new("table", 2)).unwrap();
+        assert_eq!(fm.truncate("table").unwrap(), 2);
+        assert_eq!(fm.length("table").unwrap(), 2);
+        assert_eq!(fm.free_blocks("table").unwrap(), 0);
+        assert_eq!(fm.append("table").unwrap().number(), 2);
+
+        fm.free_block(&BlockId::new("table", 0)).unwrap();
+        fm.remove_file("table").unwrap();
+        assert_eq!(fm.length("table").unwrap(), 0);
+        assert_eq!(fm.free_blocks("table").unwrap(), 0);
+        assert_eq!(fm.append("table").unwrap().number(), 0);
+    }
+
+    #[test]
+    fn test_open_file_limit() {
+        let options = FileOptions {
+            max_open_files: 2,
+            ..FileOptions::default()
+        };
+        let storage =
+            Arc::new(FsBackend::with_options("./test_open_file_limit".into(), &options).unwrap());
+        let fm = Arc::new(FileManager::with_options(storage.clone(), 400, &options).unwrap());
+
+        let mut handles = Vec::new();
+        for t in 0..4 {
+            let fm = fm.clone();
+            handles.push(thread::spawn(move || {
+                let mut page = Page::new(fm.block_size());
+                for i in 0..20 {
+                    let filename = format!("table{}", (t + i) % 6);
+                    let blk = fm.append(&filename).unwrap();
+                    page.set_int(0, i);
+                    fm.write(&blk, &mut page).unwrap();
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut page = Page::new(fm.block_size());
+        let mut blocks = 0;
+        for f in 0..6 {
+            let filename = format!("table{}", f);
+            for n in 0..fm.length(&filename).unwrap() {
+                fm.read(&BlockId::new(&filename, n), &mut page).unwrap();
+                blocks += 1;
+            }
+        }
+        assert_eq!(blocks, 80);
+        // handles in use may briefly exceed the limit, idle ones are closed on the next access
+        assert!(storage.open_file_count() <= 2);
+
+        fm.close_file("table5").unwrap();
+        assert_eq!(storage.open_file_count(), 1);
+
+        // closing a file while others append to it does not hand out a block twice
+        let appending = Arc::new(AtomicBool::new(true));
+        let closer = {
+            let (storage, appending) = (storage.clone(), appending.clone());
+            thread::spawn(move || {
+                while appending.load(Ordering::Relaxed) {
+                    storage.close("shared").unwrap();
+                }
+            })
+        };
+        let appenders: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = storage.clone();
+                thread::spawn(move || {
+                    (0..500)
+                        .map(|_| storage.append("shared", &[1; 416]).unwrap())
+                        .collect::<Vec<u64>>()
+                })
+            })
+            .collect();
+        let mut blk_nums: Vec<u64> = appenders
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        appending.store(false, Ordering::Relaxed);
+        closer.join().unwrap();
+        blk_nums.sort();
+        assert_eq!(blk_nums, (0..2000).collect::<Vec<u64>>());
+        assert_eq!(fm.length("shared").unwrap(), 2000);
+        fm.remove_file("shared").unwrap();
+        fm.close_file("table5").unwrap();
+        assert_eq!(storage.open_file_count(), 1);
+        fm.remove_file("table4").unwrap();
+        assert_eq!(storage.open_file_count(), 0);
+        assert!(!fs::exists("./test_open_file_limit/table4").unwrap());
+        assert_eq!(fm.length("table4").unwrap(), 0);
+
+        fs::remove_dir_all("test_open_file_limit").unwrap();
+    }
+
+    #[test]
+    fn test_encryption() {
+        let storage = Arc::new(MemoryBackend::new());
+        let key = EncryptionKey::new([42; 32]);
+        let options = |key: Option<EncryptionKey>| FileOptions {
+            encryption_key: key,
+            ..FileOptions::default()
+        };
+        let content = "customer data";
+        {
+            let db = SimpleDB::open(storage.clone(), 400, 8, options(Some(key.clone()))).unwrap();
+            assert!(db.file_manager().superblock().is_encrypted());
+            let fm = db.file_manager();
+            let mut page = Page::new(fm.block_size());
+            page.set_string(20, content);
+            fm.write(&BlockId::new("table", 0), &mut page).unwrap();
+            fm.write(&BlockId::new(".temp1", 0), &mut page).unwrap();
+            let lm = db.log_manager();
+            let lsn = lm.append(&content.as_bytes().to_vec()).unwrap();
+            lm.flush(lsn).unwrap();
+
+            // no file holds the plaintext
+            for filename in ["table", ".temp1", "simpledb.log"] {
+                let mut b = vec![0; storage.length(filename).unwrap() as usize];
+                storage.read_block(filename, 0, &mut b).unwrap();
+                assert!(!b.windows(content.len()).any(|w| w == content.as_bytes()));
+            }
+        }
+
+        // the right key reads everything back, including the log
+        let db = SimpleDB::open(storage.clone(), 400, 8, options(Some(key))).unwrap();
+        let fm = db.file_manager();
+        let mut page = Page::new(fm.block_size());
+        fm.read(&BlockId::new("table", 0), &mut page).unwrap();
+        assert_eq!(page.get_string(20).unwrap(), content);
+        let mut iter = db.log_manager().iterator().unwrap();
+        assert_eq!(iter.next().unwrap().unwrap().1, content.as_bytes());
+
+        // every write gets a nonce of its own, also across a reopen
+        let raw_block = |n| {
+            let mut b = vec![0; fm.physical_block_size()];
+            storage.read_block("table", n, &mut b).unwrap();
+            b
+        };
+        let nonce = |b: &[u8]| u64::from_le_bytes(b[16..24].try_into().unwrap());
+        let before = raw_block(0);
+        fm.write(&BlockId::new("table", 0), &mut page).unwrap();
+        let after = raw_block(0);
+        assert!(nonce(&after) > nonce(&before));
+        assert_ne!(before[24..], after[24..]);
+        fm.read(&BlockId::new("table", 0), &mut page).unwrap();
+        assert_eq!(page.get_string(20).unwrap(), content);
+
+        // a freed block is stored as zeros rather than as an encrypted empty page
+        let blk = fm.append("table").unwrap();
+        fm.write(&blk, &mut page).unwrap();
+        fm.free_block(&blk).unwrap();
+        assert!(raw_block(blk.number()).iter().all(|b| *b == 0));
+        assert_eq!(fm.append("table").unwrap(), blk);
+        assert!(raw_block(blk.number()).iter().all(|b| *b == 0));
+        fm.read(&blk, &mut page).unwrap();
+        assert_eq!(page.get_int(20), 0);
+        drop(db);
+
+        let open_err = |storage: Arc<MemoryBackend>, key: Option<EncryptionKey>| {
+            let err = SimpleDB::open(storage, 400, 8, options(key)).err().unwrap();
+            let err = err.get_ref().unwrap().downcast_ref::<SuperblockError>();
+            assert!(err.is_some(), "expected a superblock error");
+            format!("{}", err.unwrap())
+        };
+        assert_eq!(
+            open_err(storage.clone(), Some(EncryptionKey::new([7; 32]))),
+            SuperblockError::WrongKey.to_string()
+        );
+        assert_eq!(
+            open_err(storage, None),
+            SuperblockError::KeyRequired.to_string()
+        );
+
+        let plain = Arc::new(MemoryBackend::new());
+        SimpleDB::with_backend(plain.clone(), 400, 8).unwrap();
+        assert_eq!(
+            open_err(plain, Some(EncryptionKey::new([7; 32]))),
+            SuperblockError::NotEncrypted.to_string()
+        );
+    }
+
+    #[test]
+    fn test_compression() {
+        let storage = Arc::new(MemoryBackend::new());
+        let options = FileOptions {
+            compression: true,
+            encryption_key: Some(EncryptionKey::new([1; 32])),
+            ..FileOptions::default()
+        };
+        let content = |i: u64| format!("customer {} lives in Tokyo", i % 3).repeat(10);
+        {
+            let db = SimpleDB::open(storage.clone(), 400, 8, options.clone()).unwrap();
+            let fm = db.file_manager();
+            assert!(fm.is_compressed());
+            let mut page = Page::new(fm.block_size());
+            for i in 0..10 {
+                let blk = fm.append("table").unwrap();
+                page.set_string(0, &content(i));
+                fm.write(&blk, &mut page).unwrap();
+            }
+            assert_eq!(fm.length("table").unwrap(), 10);
+            assert!(storage.length("table").unwrap() < 10 * fm.physical_block_size() as u64 / 2);
+            assert!(fm.compression_stats().ratio() > 2.0);
+
+            // a block that no longer fits its slot moves to the end of the file
+            let mut noise = Page::new(fm.block_size());
+            for pos in (0..fm.block_size() - 4).step_by(4) {
+                noise.set_int(pos, (pos as u32).wrapping_mul(2654435761));
+            }
+            let stored = storage.length("table").unwrap();
+            fm.write(&BlockId::new("table", 3), &mut noise).unwrap();
+            assert!(storage.length("table").unwrap() > stored);
+            fm.read(&BlockId::new("table", 3), &mut page).unwrap();
+            assert_eq!(page.get_int(8), noise.get_int(8));
+
+            // every write goes to a fresh slot, the old one is reused once the map is synced
+            fm.sync("table").unwrap();
+            fm.write(&BlockId::new("table", 3), &mut noise).unwrap();
+            fm.sync("table").unwrap();
+            let stored = storage.length("table").unwrap();
+            for _ in 0..5 {
+                fm.write(&BlockId::new("table", 3), &mut noise).unwrap();
+                fm.sync("table").unwrap();
+            }
+            assert_eq!(storage.length("table").unwrap(), stored);
+            fm.read(&BlockId::new("table", 3), &mut page).unwrap();
+            assert_eq!(page.get_int(8), noise.get_int(8));
+
+            let lm = db.log_manager();
+            let lsn = lm.append(&b"log record".to_vec()).unwrap();
+            lm.flush(lsn).unwrap();
+        }
+
+        // compression is fixed when the database is created
+        let options = FileOptions {
+            compression: false,
+            ..options
+        };
+        let db = SimpleDB::open(storage.clone(), 400, 8, options).unwrap();
+        let fm = db.file_manager();
+        assert!(fm.is_compressed());
+        assert_eq!(fm.length("table").unwrap(), 10);
+        let mut page = Page::new(fm.block_size());
+        for i in (0..10).filter(|i| *i != 3) {
+            fm.read(&BlockId::new("table", i), &mut page).unwrap();
+            assert_eq!(page.get_string(0).unwrap(), content(i));
+        }
+        let mut iter = db.log_manager().iterator().unwrap();
+        assert_eq!(iter.next().unwrap().unwrap().1, b"log record");
+
+        // blocks past the end of the file read as empty pages, freed blocks can be cut off
+        fm.read(&BlockId::new("table", 20), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 0);
+        fm.free_block(&BlockId::new("table", 9)).unwrap();
+        assert_eq!(fm.truncate("table").unwrap(), 1);
+        assert_eq!(fm.length("table").unwrap(), 9);
+        assert_eq!(fm.append("table").unwrap().number(), 9);
+    }
+
+    #[test]
+    fn test_mmap_reads() {
+        let options = FileOptions {
+            mmap_reads: true,
+            ..FileOptions::default()
+        };
+        let db = SimpleDB::with_options("./test_mmap_reads", 400, 8, options).unwrap();
+        let fm = db.file_manager();
+        let mut page = Page::new(fm.block_size());
+        for round in 0..3 {
+            // every round grows the file past the current mapping
+            for i in 0..4 {
+                let blk = fm.append("table").unwrap();
+                page.set_int(0, round * 4 + i);
+                fm.write(&blk, &mut page).unwrap();
+            }
+            for n in 0..fm.length("table").unwrap() {
+                fm.read(&BlockId::new("table", n), &mut page).unwrap();
+                assert_eq!(page.get_int(0), n as u32);
+            }
+        }
+
+        // writes to blocks already mapped are seen by later reads
+        page.set_int(0, 99);
+        fm.write(&BlockId::new("table", 1), &mut page).unwrap();
+        fm.read(&BlockId::new("table", 1), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 99);
+
+        // shrinking the file drops the mapping
+        fm.free_block(&BlockId::new("table", 11)).unwrap();
+        assert_eq!(fm.truncate("table").unwrap(), 1);
+        fm.read(&BlockId::new("table", 11), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 0);
+        let mut pages = vec![Page::new(fm.block_size()), Page::new(fm.block_size())];
+        let blks = [BlockId::new("table", 9), BlockId::new("table", 10)];
+        fm.read_many(&blks, &mut pages).unwrap();
+        assert_eq!(pages[1].get_int(0), 10);
+
+        fs::remove_dir_all("test_mmap_reads").unwrap();
+    }
+
+    #[test]
+    fn test_io_stats() {
+        let db = SimpleDB::new_in_memory(400, 8).unwrap();
+        let fm = db.file_manager();
+        fm.reset_stats();
+        let mut page = Page::new(fm.block_size());
+        for _ in 0..3 {
+            let blk = fm.append("table").unwrap();
+            fm.write(&blk, &mut page).unwrap();
+        }
+        for n in 0..3 {
+            fm.read(&BlockId::new("table", n), &mut page).unwrap();
+        }
+        let mut pages = vec![Page::new(fm.block_size()), Page::new(fm.block_size())];
+        let blks = [BlockId::new(".temp1", 0), BlockId::new(".temp1", 1)];
+        fm.write_many(&blks, &pages).unwrap();
+        fm.read_many(&blks, &mut pages).unwrap();
+        let lm = db.log_manager();
+        let lsn = lm.append(&b"record".to_vec()).unwrap();
+        lm.flush(lsn).unwrap();
+
+        let stats = fm.stats();
+        let table = stats.file("table");
+        assert_eq!((table.appends, table.writes, table.reads), (3, 3, 3));
+        assert_eq!(table.bytes_read, 3 * fm.physical_block_size() as u64);
+        assert_eq!(table.read_latency.count(), 3);
+        assert_eq!(table.write_latency.count(), 6);
+        assert!(table.read_latency.percentile(0.99).is_some());
+
+        let by_kind = stats.by_kind();
+        assert_eq!(by_kind[&FileKind::Temp].writes, 2);
+        assert_eq!(by_kind[&FileKind::Temp].reads, 2);
+        assert_eq!(by_kind[&FileKind::Log].writes, 1);
+        assert_eq!(by_kind[&FileKind::Log].syncs, 1);
+        assert_eq!(stats.sync.full_syncs, 1);
+        assert_eq!(stats.total().writes, 6);
+
+        fm.reset_stats();
+        let stats = fm.stats();
+        assert_eq!(stats.total(), Default::default());
+        assert_eq!(stats.sync, Default::default());
+    }
+
+    #[test]
+    fn test_segmented_files() {
+        let dir = "./test_segmented_files";
+        // not a multiple of the block size, so some blocks straddle two segments
+        let options = FileOptions {
+            segment_size: 1000,
+            ..FileOptions::default()
+        };
+        {
+            let db = SimpleDB::with_options(dir, 400, 8, options.clone()).unwrap();
+            let fm = db.file_manager();
+            let mut page = Page::new(fm.block_size());
+            for i in 0..10 {
+                let blk = fm.append("table").unwrap();
+                page.set_int(0, i);
+                fm.write(&blk, &mut page).unwrap();
+            }
+            fm.write(&BlockId::new(".temp1", 3), &mut page).unwrap();
+            fm.sync("table").unwrap();
+            let lm = db.log_manager();
+            let lsn = lm.append(&b"record".to_vec()).unwrap();
+            lm.flush(lsn).unwrap();
+        }
+        let segments = 10 * 416 / 1000 + 1;
+        for n in 0..segments {
+            assert!(fs::exists(format!("{}/table.{}", dir, n)).unwrap());
+        }
+        assert!(!fs::exists(format!("{}/table", dir)).unwrap());
+        assert!(fs::exists(format!("{}/{}", dir, CONTROL_FILE)).unwrap());
+
+        let err = SimpleDB::new(dir, 400, 8).err().unwrap();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SuperblockError>(),
+            Some(&SuperblockError::SegmentSizeMismatch {
+                stored: 1000,
+                requested: 0
+            })
+        );
+
+        let storage = Arc::new(FsBackend::with_options(dir.into(), &options).unwrap());
+        let db = SimpleDB::open(storage.clone(), 400, 8, options).unwrap();
+        let fm = db.file_manager();
+        assert_eq!(fm.length("table").unwrap(), 10);
+        let mut page = Page::new(fm.block_size());
+        for n in 0..10 {
+            fm.read(&BlockId::new("table", n), &mut page).unwrap();
+            assert_eq!(page.get_int(0), n as u32);
+        }
+        let blks: Vec<BlockId> = (0..10).map(|n| BlockId::new("table", n)).collect();
+        let mut pages: Vec<Page> = (0..10).map(|_| Page::new(fm.block_size())).collect();
+        fm.read_many(&blks, &mut pages).unwrap();
+        assert_eq!(pages[7].get_int(0), 7);
+        let mut iter = db.log_manager().iterator().unwrap();
+        assert_eq!(iter.next().unwrap().unwrap().1, b"record");
+
+        // temp files are gone with all their segments, the files are listed by their own name
+        let files = storage.files().unwrap();
+        assert!(files.contains(&"table".to_string()));
+        assert!(!files
+            .iter()
+            .any(|f| f.starts_with(TEMP_PREFIX) || f.starts_with("table.")));
+        assert_eq!(storage.segment_paths("table").unwrap().len(), segments);
+
+        // cutting blocks off drops the segments past the new end
+        for n in 4..10 {
+            fm.free_block(&BlockId::new("table", n)).unwrap();
+        }
+        assert_eq!(fm.truncate("table").unwrap(), 6);
+        assert_eq!(fm.length("table").unwrap(), 4);
+        assert_eq!(storage.segment_paths("table").unwrap().len(), 2);
+        fm.read(&BlockId::new("table", 3), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 3);
+
+        // reading past the end creates no segment, writing past it may skip some
+        fm.read(&BlockId::new("table", 7), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 0);
+        assert_eq!(fm.length("table").unwrap(), 4);
+        assert_eq!(storage.segment_paths("table").unwrap().len(), 2);
+        page.set_int(0, 8);
+        fm.write(&BlockId::new("table", 8), &mut page).unwrap();
+        assert_eq!(storage.segment_paths("table").unwrap().len(), 3);
+        assert_eq!(fm.length("table").unwrap(), 9);
+
+        fm.remove_file("table").unwrap();
+        assert!(storage.segment_paths("table").unwrap().is_empty());
+        assert_eq!(fm.length("table").unwrap(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tablespaces() {
+        let dir = "./test_tablespaces";
+        // relative locations are inside the database directory
+        let fast = format!("{}/fast", dir);
+        let archive = format!("{}/archive", dir);
+        let options = FileOptions {
+            tablespaces: vec![("fast".to_string(), "fast".to_string())],
+            temp_tablespace: Some("fast".to_string()),
+            ..FileOptions::default()
+        };
+        {
+            let db = SimpleDB::with_options(dir, 400, 8, options.clone()).unwrap();
+            let fm = db.file_manager();
+            fm.set_tablespace("hot", "fast").unwrap();
+            let mut page = Page::new(fm.block_size());
+            for i in 0..3 {
+                let blk = fm.append("hot").unwrap();
+                page.set_int(0, i);
+                fm.write(&blk, &mut page).unwrap();
+                let blk = fm.append("cold").unwrap();
+                fm.write(&blk, &mut page).unwrap();
+            }
+            fm.free_block(&BlockId::new("hot", 1)).unwrap();
+            fm.write(&BlockId::new(".temp1", 0), &mut page).unwrap();
+            fm.sync("hot").unwrap();
+            fm.sync("cold").unwrap();
+            assert_eq!(fm.tablespace_of("hot"), "fast");
+            assert_eq!(fm.tablespace_of(".temp2"), "fast");
+            assert_eq!(fm.tablespace_of("cold"), "default");
+            assert!(fm.set_tablespace("cold", "nowhere").is_err());
+        }
+        assert!(fs::exists(format!("{}/hot", fast)).unwrap());
+        assert!(fs::exists(format!("{}/hot.free", fast)).unwrap());
+        assert!(fs::exists(format!("{}/.temp1", fast)).unwrap());
+        assert!(!fs::exists(format!("{}/hot", dir)).unwrap());
+        assert!(fs::exists(format!("{}/cold", dir)).unwrap());
+        assert!(fs::exists(format!("{}/{}", dir, TABLESPACE_FILE)).unwrap());
+
+        // the catalog remembers the tablespaces, temp files are cleaned up in all of them
+        let db = SimpleDB::new(dir, 400, 8).unwrap();
+        let fm = db.file_manager();
+        assert!(!fs::exists(format!("{}/.temp1", fast)).unwrap());
+        assert_eq!(
+            fm.tablespaces(),
+            vec![("fast".to_string(), "fast".to_string())]
+        );
+        assert!(!fs::exists(format!("{}/{}.new", dir, TABLESPACE_FILE)).unwrap());
+        assert_eq!(fm.tablespace_of("hot"), "fast");
+        assert_eq!(fm.tablespace_of(".temp1"), "default");
+        assert_eq!(fm.length("hot").unwrap(), 3);
+        assert_eq!(fm.free_blocks("hot").unwrap(), 1);
+        let mut page = Page::new(fm.block_size());
+        fm.read(&BlockId::new("hot", 2), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 2);
+        assert!(fm.create_tablespace("fast", "archive").is_err());
+
+        // files would be copied onto themselves in the database directory or another tablespace
+        for location in [".", "./fast", "../test_tablespaces"] {
+            let err = fm.create_tablespace("same", location).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(fm.tablespaces().len(), 1);
+        assert!(fm.archive_file("cold", ".").is_err());
+        assert_eq!(fm.length("cold").unwrap(), 3);
+
+        // moving a file takes its blocks and free list along
+        let location = fs::canonicalize(dir).unwrap().join("archive");
+        fm.create_tablespace("archive", location.to_str().unwrap())
+            .unwrap();
+        fm.set_tablespace("hot", "archive").unwrap();
+        fm.set_tablespace("cold", "archive").unwrap();
+        assert!(!fs::exists(format!("{}/hot", fast)).unwrap());
+        assert!(!fs::exists(format!("{}/cold", dir)).unwrap());
+        assert!(fs::exists(format!("{}/hot.free", archive)).unwrap());
+        fm.read(&BlockId::new("hot", 2), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 2);
+        fm.read(&BlockId::new("cold", 1), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 1);
+        assert_eq!(fm.append("hot").unwrap().number(), 1);
+
+        fm.set_tablespace("cold", "default").unwrap();
+        assert!(fs::exists(format!("{}/cold", dir)).unwrap());
+        assert_eq!(fm.length("cold").unwrap(), 3);
+        fm.remove_file("hot").unwrap();
+        assert!(!fs::exists(format!("{}/hot", archive)).unwrap());
+        assert_eq!(fm.tablespace_of("hot"), "default");
+
+        // tablespaces work the same on the in-memory backend
+        let storage = Arc::new(MemoryBackend::new());
+        {
+            let fm = FileManager::with_options(storage.clone(), 400, &options).unwrap();
+            fm.set_tablespace("hot", "fast").unwrap();
+            let blk = fm.append("hot").unwrap();
+            page.set_int(0, 7);
+            fm.write(&blk, &mut page).unwrap();
+        }
+        assert_eq!(storage.length("hot").unwrap(), 0);
+        let fm = FileManager::with_backend(storage, 400).unwrap();
+        fm.read(&BlockId::new("hot", 0), &mut page).unwrap();
+        assert_eq!(page.get_int(0), 7);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,5 @@
+pub mod app;
+pub mod buffer;
+pub mod file;
+pub mod log;
+pub mod tx;
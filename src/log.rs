@@ -1,3 +1,7 @@
+pub mod forward_log_iterator;
+pub mod fragment;
+pub mod group_commit;
 pub mod log_iterator;
 pub mod log_manager;
+pub mod segments;
 pub mod test;
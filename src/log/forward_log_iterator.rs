@@ -0,0 +1,139 @@
+use std::io::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::file::block_id::BlockId;
+use crate::file::page::Page;
+
+use super::fragment::{read_fragments, Fragment, FragmentKind};
+use super::segments::LogSegments;
+
+// Walks the log forwards, from the first record at or after an lsn to the end of the log.
+// The end is checked as the iterator goes, so records flushed meanwhile are returned too.
+// Records split over several blocks are put back together.
+// The log manager writes filled blocks some time after it moved on to a new one, so a block
+// read from disk may still grow; the iterator only moves past a block once it is complete.
+pub struct ForwardLogIterator {
+    segments: Arc<LogSegments>,
+    log_file: String,
+    // lsn up to which the log is on disk, kept up to date by the log manager
+    saved_lsn: Arc<AtomicU64>,
+    // next block to read
+    next_block: u64,
+    // lsn the next fragment looked at has to have at least
+    min_lsn: u64,
+    // fragments of the current block not looked at yet, the newest first so that pop gives the oldest
+    fragments: Vec<Fragment>,
+    done: bool,
+}
+
+impl ForwardLogIterator {
+    pub fn new(
+        segments: Arc<LogSegments>,
+        log_file: &str,
+        saved_lsn: Arc<AtomicU64>,
+        start_lsn: u64,
+    ) -> Result<Self, Error> {
+        let block_size = segments.block_size() as u64;
+        // records in removed segments are not there to be returned
+        let next_block = (start_lsn.saturating_sub(1) / block_size).max(segments.first_block());
+        let mut l = Self {
+            segments,
+            log_file: log_file.to_string(),
+            saved_lsn,
+            next_block,
+            min_lsn: start_lsn,
+            fragments: Vec::new(),
+            done: false,
+        };
+        l.fill()?;
+        Ok(l)
+    }
+
+    // reads blocks until one has fragments that were not looked at yet, or the log ends
+    fn fill(&mut self) -> Result<(), Error> {
+        let block_size = self.segments.block_size();
+        loop {
+            if self.next_block >= self.segments.length()? {
+                return Ok(());
+            }
+            // loaded before the block is read, so that everything up to it is in what is read
+            let saved_lsn = self.saved_lsn.load(Ordering::Acquire);
+            let block_id = BlockId::new(&self.log_file, self.next_block);
+            let mut page = Page::new(block_size);
+            self.segments.read(&block_id, &mut page)?;
+            self.fragments = read_fragments(&page, &block_id, block_size)?;
+            // the block is complete once records after it are on disk, or if a split record
+            // continues in the next block, as that fills it
+            let complete = saved_lsn > (self.next_block + 1) * block_size as u64
+                || matches!(
+                    self.fragments.first().map(|f| f.kind),
+                    Some(FragmentKind::First | FragmentKind::Middle)
+                );
+            self.fragments.retain(|f| f.lsn >= self.min_lsn);
+            if !complete {
+                // read again next time, in case it grew
+                return Ok(());
+            }
+            self.next_block += 1;
+            if !self.fragments.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn next_fragment(&mut self) -> Result<Option<Fragment>, Error> {
+        if self.fragments.is_empty() {
+            self.fill()?;
+        }
+        let fragment = self.fragments.pop();
+        if let Some(f) = &fragment {
+            self.min_lsn = f.lsn + 1;
+        }
+        Ok(fragment)
+    }
+
+    fn next_record(&mut self) -> Result<Option<(u64, Vec<u8>)>, Error> {
+        // the lsn and the fragments so far of a split record
+        let mut partial: Option<(u64, Vec<u8>)> = None;
+        while let Some(fragment) = self.next_fragment()? {
+            match (fragment.kind, partial.as_mut()) {
+                // a record started before was never finished, as its last fragment was not
+                // flushed before a crash, and is dropped
+                (FragmentKind::Full, _) => return Ok(Some((fragment.lsn, fragment.bytes))),
+                (FragmentKind::First, _) => partial = Some((fragment.lsn, fragment.bytes)),
+                (FragmentKind::Middle, Some((_, record))) => record.extend(fragment.bytes),
+                (FragmentKind::Last, Some((_, record))) => {
+                    record.extend(fragment.bytes);
+                    return Ok(partial);
+                }
+                // the rest of a record that started before the lsn the iterator started at
+                (FragmentKind::Middle | FragmentKind::Last, None) => {}
+            }
+        }
+        // the log ends in the middle of a record, which is looked at again on the next call
+        // in case the rest of it is flushed meanwhile
+        if let Some((lsn, _)) = partial {
+            self.min_lsn = lsn;
+            self.next_block = (lsn - 1) / self.segments.block_size() as u64;
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for ForwardLogIterator {
+    type Item = Result<(u64, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
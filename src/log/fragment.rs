@@ -0,0 +1,173 @@
+use std::io::{Error, ErrorKind};
+use std::mem::size_of;
+
+use crate::file::block_id::BlockId;
+use crate::file::checksum::crc32;
+use crate::file::page::Page;
+
+// A log block starts with the offset of its most recent fragment, the boundary; fragments are
+// written from the end of the block towards its start, each one as
+// | checksum (u32) | lsn (u64) | kind (u32) | length (u32) | bytes |
+// The checksum is a CRC-32 over the rest of the fragment, so that a fragment damaged by a torn
+// write of its block is told apart from an intact one.
+// A record that fits into a block is a single Full fragment. A larger one is split into a First
+// fragment, any number of Middle ones and a Last one, filling consecutive blocks.
+// The lsn of a fragment is its position in the log: the number of the block times the block size
+// plus the bytes of the block used up to and including the fragment. The lsn of a record is that
+// of its first fragment.
+pub const FRAGMENT_HEADER_SIZE: usize = size_of::<u64>() + 3 * size_of::<u32>();
+const LSN_POS: usize = size_of::<u32>();
+const KIND_POS: usize = LSN_POS + size_of::<u64>();
+const LENGTH_POS: usize = KIND_POS + size_of::<u32>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentKind {
+    Full = 0,
+    First = 1,
+    Middle = 2,
+    Last = 3,
+}
+
+impl FragmentKind {
+    fn from_u32(n: u32) -> Option<Self> {
+        match n {
+            0 => Some(Self::Full),
+            1 => Some(Self::First),
+            2 => Some(Self::Middle),
+            3 => Some(Self::Last),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    // where the fragment starts in its block
+    pub offset: usize,
+    pub lsn: u64,
+    pub kind: FragmentKind,
+    pub bytes: Vec<u8>,
+}
+
+// lsn of the fragment starting at offset in the block
+pub fn lsn_at(block_size: usize, block: &BlockId, offset: u32) -> u64 {
+    block.number() * block_size as u64 + (block_size as u64 - offset as u64)
+}
+
+// whether a fragment of len bytes fits into the free space of the block
+pub fn fits(page: &Page, len: usize) -> bool {
+    page.get_int(0) as usize >= size_of::<u32>() + FRAGMENT_HEADER_SIZE + len
+}
+
+// most bytes of a fragment that fit into the free space of the block
+pub fn room(page: &Page) -> usize {
+    (page.get_int(0) as usize).saturating_sub(size_of::<u32>() + FRAGMENT_HEADER_SIZE)
+}
+
+/**
+ * writes a fragment in front of the ones already in the block and returns its lsn.
+ * the bytes have to fit, see fits.
+ */
+pub fn write_fragment(
+    page: &mut Page,
+    block_id: &BlockId,
+    block_size: usize,
+    kind: FragmentKind,
+    bytes: &[u8],
+) -> u64 {
+    assert!(
+        fits(page, bytes.len()),
+        "the fragment does not fit the block"
+    );
+    let offset = page.get_int(0) as usize - FRAGMENT_HEADER_SIZE - bytes.len();
+    let lsn = lsn_at(block_size, block_id, offset as u32);
+    page.try_set_u64(offset + LSN_POS, lsn).unwrap();
+    page.set_int(offset + KIND_POS, kind as u32);
+    page.set_bytes(offset + LENGTH_POS, bytes);
+    let end = offset + FRAGMENT_HEADER_SIZE + bytes.len();
+    let checksum = crc32(&page.contents()[offset + LSN_POS..end]);
+    page.set_int(offset, checksum);
+    page.set_int(0, offset as u32);
+    lsn
+}
+
+/**
+ * returns the fragments in a log block, the newest first,
+ * which is the order in which they are laid out from the boundary on.
+ */
+pub fn read_fragments(
+    page: &Page,
+    block_id: &BlockId,
+    block_size: usize,
+) -> Result<Vec<Fragment>, Error> {
+    let boundary = page.get_int(0) as usize;
+    if boundary < size_of::<u32>() || boundary > block_size {
+        return Err(corrupted(block_id, 0));
+    }
+    read_fragments_from(page, block_id, block_size, boundary)
+}
+
+/**
+ * finds the intact fragments of a block that may have been damaged: the longest run of them
+ * that starts at or after the offset from and ends with the block. returns the offset the run
+ * starts at, which is the block size if there is none, and its fragments, the newest first.
+ */
+pub fn intact_fragments(
+    page: &Page,
+    block_id: &BlockId,
+    block_size: usize,
+    from: usize,
+) -> (usize, Vec<Fragment>) {
+    for pos in from.max(size_of::<u32>())..block_size {
+        if let Ok(fragments) = read_fragments_from(page, block_id, block_size, pos) {
+            return (pos, fragments);
+        }
+    }
+    (block_size, Vec::new())
+}
+
+// reads the fragments from pos to the end of the block, checking each one
+fn read_fragments_from(
+    page: &Page,
+    block_id: &BlockId,
+    block_size: usize,
+    mut pos: usize,
+) -> Result<Vec<Fragment>, Error> {
+    let mut fragments = Vec::new();
+    while pos < block_size {
+        if pos + FRAGMENT_HEADER_SIZE > block_size {
+            return Err(corrupted(block_id, pos));
+        }
+        let len = page.get_int(pos + LENGTH_POS) as usize;
+        let end = pos + FRAGMENT_HEADER_SIZE + len;
+        if end > block_size {
+            return Err(corrupted(block_id, pos));
+        }
+        let lsn = page.try_get_u64(pos + LSN_POS).unwrap();
+        let kind = FragmentKind::from_u32(page.get_int(pos + KIND_POS));
+        let checksum = crc32(&page.contents()[pos + LSN_POS..end]);
+        match kind {
+            Some(kind)
+                if checksum == page.get_int(pos)
+                    && lsn == lsn_at(block_size, block_id, pos as u32) =>
+            {
+                fragments.push(Fragment {
+                    offset: pos,
+                    lsn,
+                    kind,
+                    bytes: page.get_bytes(pos + LENGTH_POS).to_vec(),
+                })
+            }
+            _ => return Err(corrupted(block_id, pos)),
+        }
+        pos = end;
+    }
+    Ok(fragments)
+}
+
+pub fn corrupted(block_id: &BlockId, pos: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("corrupted log record at offset {} of {}", pos, block_id),
+    )
+}
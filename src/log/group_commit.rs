@@ -0,0 +1,138 @@
+use std::{
+    collections::BTreeMap,
+    io::Error,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::log_manager::LogManager;
+
+// Makes commit records durable for many committers with few flushes.
+// A committer waits until the log is flushed up to the lsn of its commit record. The first one
+// to find no flush under way becomes the leader: it waits for the commit delay so that others can
+// join, flushes everything appended so far and releases every committer that flush covered.
+// Committers that arrive meanwhile wait for the next flush, led by one of them.
+pub struct GroupCommit {
+    log_manager: Arc<LogManager>,
+    commit_delay: Duration,
+    state: Mutex<CommitState>,
+    flushed: Condvar,
+}
+
+#[derive(Default)]
+struct CommitState {
+    // everything up to this lsn is known to be on disk
+    flushed_lsn: u64,
+    // whether a leader is flushing at the moment
+    leading: bool,
+    // lsn -> number of committers waiting for it
+    waiting: BTreeMap<u64, u64>,
+    stats: GroupCommitStats,
+}
+
+// How committers were batched, see GroupCommit::stats.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GroupCommitStats {
+    pub commits: u64,
+    // flushes done by leaders, not counting those that found the log on disk already
+    pub flushes: u64,
+    // batch size -> number of flushes that released that many committers
+    pub batch_sizes: BTreeMap<u64, u64>,
+}
+
+impl GroupCommitStats {
+    pub fn largest_batch(&self) -> u64 {
+        self.batch_sizes.keys().last().copied().unwrap_or(0)
+    }
+
+    pub fn average_batch(&self) -> f64 {
+        match self.flushes {
+            0 => 0.0,
+            n => self.batch_sizes.iter().map(|(s, c)| s * c).sum::<u64>() as f64 / n as f64,
+        }
+    }
+}
+
+impl GroupCommit {
+    pub fn new(log_manager: Arc<LogManager>, commit_delay: Duration) -> Self {
+        let flushed_lsn = log_manager.get_last_saved_lsn();
+        Self {
+            log_manager,
+            commit_delay,
+            state: Mutex::new(CommitState {
+                flushed_lsn,
+                ..Default::default()
+            }),
+            flushed: Condvar::new(),
+        }
+    }
+
+    /**
+     * returns once the log is on disk up to lsn, the lsn of a commit record.
+     * if the flush fails, the error goes to the leader and a waiting committer retries it.
+     */
+    pub fn commit(&self, lsn: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.stats.commits += 1;
+        if lsn <= state.flushed_lsn {
+            return Ok(());
+        }
+        *state.waiting.entry(lsn).or_insert(0) += 1;
+        while state.leading {
+            state = self.flushed.wait(state).unwrap();
+            if lsn <= state.flushed_lsn {
+                return Ok(());
+            }
+        }
+        state.leading = true;
+        drop(state);
+
+        if !self.commit_delay.is_zero() {
+            thread::sleep(self.commit_delay);
+        }
+        let target = self.log_manager.latest_lsn();
+        // the log may have been flushed past the lsn by someone else, e.g. a buffer being written
+        let needed = target > self.log_manager.get_last_saved_lsn();
+        let flushed = self.log_manager.flush(target).map(|_| (target, needed));
+
+        let mut state = self.state.lock().unwrap();
+        state.leading = false;
+        let result = match flushed {
+            Ok((target, needed)) => {
+                state.flushed_lsn = state.flushed_lsn.max(target);
+                let first_waiting = state.flushed_lsn + 1;
+                let still_waiting = state.waiting.split_off(&first_waiting);
+                let batch = std::mem::replace(&mut state.waiting, still_waiting);
+                if needed {
+                    state.stats.flushes += 1;
+                    *state
+                        .stats
+                        .batch_sizes
+                        .entry(batch.values().sum())
+                        .or_insert(0) += 1;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if let Some(n) = state.waiting.get_mut(&lsn) {
+                    *n -= 1;
+                    if *n == 0 {
+                        state.waiting.remove(&lsn);
+                    }
+                }
+                Err(e)
+            }
+        };
+        self.flushed.notify_all();
+        result
+    }
+
+    pub fn commit_delay(&self) -> Duration {
+        self.commit_delay
+    }
+
+    pub fn stats(&self) -> GroupCommitStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+}
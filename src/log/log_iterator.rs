@@ -1,55 +1,104 @@
 use std::io::Error;
-use std::mem::size_of;
 use std::sync::Arc;
 
 use crate::file::block_id::BlockId;
-use crate::file::file_manager::FileManager;
 use crate::file::page::Page;
+
+use super::fragment::{corrupted, read_fragments, Fragment, FragmentKind};
+use super::segments::LogSegments;
+
+// Walks the log backwards, from the most recent record to the first one that was not removed.
+// Records split over several blocks are put back together.
 pub struct LogIterator {
-    file_manager: Arc<FileManager>,
+    segments: Arc<LogSegments>,
     block_id: BlockId,
-    page: Page,
-    current_pos: u32,
-    boundary: u32,
+    // fragments of the current block not looked at yet, the oldest first so that pop gives the newest
+    fragments: Vec<Fragment>,
+    done: bool,
 }
 
 impl LogIterator {
-    pub fn new(file_manager: Arc<FileManager>, current_block: &BlockId) -> Result<Self, Error> {
-        let page = Page::new(file_manager.block_size());
-        let mut l = Self {
-            file_manager,
+    /**
+     * starts at the current block of the log, taken from its page in memory, which the log
+     * manager may have cut a torn tail off that is still on disk.
+     */
+    pub fn new(
+        segments: Arc<LogSegments>,
+        current_block: &BlockId,
+        current_page: &Page,
+    ) -> Result<Self, Error> {
+        let mut fragments = read_fragments(current_page, current_block, segments.block_size())?;
+        fragments.reverse();
+        Ok(Self {
+            segments,
             block_id: current_block.clone(),
-            page,
-            current_pos: 0,
-            boundary: 0,
-        };
-        l.move_to_block(current_block)?;
-        Ok(l)
+            fragments,
+            done: false,
+        })
     }
+
     pub fn has_next(&self) -> bool {
-        (self.current_pos < self.file_manager.block_size() as u32) || self.block_id.number() > 0
+        !self.done
+            && (!self.fragments.is_empty() || self.block_id.number() > self.segments.first_block())
     }
+
     fn move_to_block(&mut self, block_id: &BlockId) -> Result<(), Error> {
-        self.file_manager.read(block_id, &mut self.page)?;
-        self.boundary = self.page.get_int(0);
-        self.current_pos = self.boundary;
+        let mut page = Page::new(self.segments.block_size());
+        self.segments.read(block_id, &mut page)?;
+        self.fragments = read_fragments(&page, block_id, self.segments.block_size())?;
+        self.fragments.reverse();
         Ok(())
     }
+
+    // the next fragment going backwards, None at the start of the log
+    fn previous_fragment(&mut self) -> Result<Option<Fragment>, Error> {
+        while self.fragments.is_empty() {
+            if !self.has_next() {
+                return Ok(None);
+            }
+            self.block_id = BlockId::new(self.block_id.filename(), self.block_id.number() - 1);
+            self.move_to_block(&self.block_id.clone())?;
+        }
+        Ok(self.fragments.pop())
+    }
+
+    fn next_record(&mut self) -> Result<Option<(u64, Vec<u8>)>, Error> {
+        // pieces of a split record, the last one first
+        let mut pieces: Vec<Vec<u8>> = Vec::new();
+        while let Some(fragment) = self.previous_fragment()? {
+            match (fragment.kind, pieces.is_empty()) {
+                (FragmentKind::Full, true) => return Ok(Some((fragment.lsn, fragment.bytes))),
+                (FragmentKind::Last, true) => pieces.push(fragment.bytes),
+                (FragmentKind::Middle, false) => pieces.push(fragment.bytes),
+                (FragmentKind::First, false) => {
+                    pieces.push(fragment.bytes);
+                    pieces.reverse();
+                    return Ok(Some((fragment.lsn, pieces.concat())));
+                }
+                // fragments of a record whose last fragment never reached the log
+                (FragmentKind::First | FragmentKind::Middle, true) => {}
+                (FragmentKind::Full | FragmentKind::Last, false) => {
+                    return Err(corrupted(&self.block_id, 0))
+                }
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl Iterator for LogIterator {
-    type Item = Vec<u8>;
+    type Item = Result<(u64, Vec<u8>), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.has_next() {
+        if self.done {
             return None;
         }
-        if self.current_pos == self.file_manager.block_size() as u32 {
-            self.block_id = BlockId::new(self.block_id.filename(), self.block_id.number() - 1);
-            self.move_to_block(&self.block_id.clone()).ok();
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
         }
-        let rec = self.page.get_bytes(self.current_pos as usize);
-        self.current_pos += rec.len() as u32 + size_of::<u32>() as u32;
-        Some(rec.to_vec())
     }
 }
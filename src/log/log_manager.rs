@@ -1,52 +1,133 @@
-use std::{io::Error, mem::size_of, sync::Arc};
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind},
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+};
 
-use crate::file::{block_id::BlockId, file_manager::FileManager, page::Page};
+use crate::file::{block_id::BlockId, file_manager::FileManager, options::FileOptions, page::Page};
 
+use super::forward_log_iterator::ForwardLogIterator;
+use super::fragment::{
+    corrupted, fits, intact_fragments, lsn_at, read_fragments, room, write_fragment, Fragment,
+    FragmentKind, FRAGMENT_HEADER_SIZE,
+};
 use super::log_iterator::LogIterator;
+use super::segments::LogSegments;
 
+// filled log blocks kept in memory until they are written at most; appends wait for a flush
+// when there are more, unless a single record needs more blocks than that
+const LOG_BUFFERS: usize = 4;
+
+// Appends records to the log file, see fragment.rs for how they are laid out.
+// The lsn of a record is derived from its position in the log, so it grows with every record
+// and carries on across restarts.
+// Records are appended to the current block in memory. A filled block is queued and appending
+// goes on in a new one; a flush extends the log file by the new blocks, writes the queued blocks
+// and the current one and syncs the log without holding the lock, so appends carry on meanwhile.
+// One thread flushes at a time, others wanting a flush wait for it.
+// The log may be split into segment files, see segments.rs; once the oldest record still needed
+// is declared, the segments before it are let go.
 pub struct LogManager {
-    file_manager: Arc<FileManager>,
+    segments: Arc<LogSegments>,
     log_file: String,
+    // where segments that are let go are moved to, they are deleted if None
+    archive: Option<String>,
+    // lsn of the oldest record still needed, 0 if all of them are
+    retention_lsn: AtomicU64,
+    state: Mutex<LogState>,
+    // notified when a flush ends
+    flush_done: Condvar,
+    // lsn up to which the log is on disk, shared with the forward iterators
+    latest_saved_lsn: Arc<AtomicU64>,
+    // bytes cut off the end of the log on open, see recover_tail
+    discarded_bytes: u64,
+}
+
+struct LogState {
     log_page: Page,
     current_block: BlockId,
+    // blocks filled since the last flush started, the oldest first
+    filled: VecDeque<(BlockId, Page)>,
     // lsn: log sequence number
-    latest_lsn: u32,
-    latest_saved_lsn: u32,
+    latest_lsn: u64,
+    // whether a thread is writing out the log
+    flushing: bool,
 }
 
 impl LogManager {
     pub fn new(file_manager: Arc<FileManager>, log_file: &str) -> Result<Self, Error> {
-        let mut log_page = Page::new(file_manager.block_size());
-        let log_size = file_manager.length(log_file)?;
+        Self::with_options(file_manager, log_file, &FileOptions::default())
+    }
+
+    pub fn with_options(
+        file_manager: Arc<FileManager>,
+        log_file: &str,
+        options: &FileOptions,
+    ) -> Result<Self, Error> {
+        // the segment size is fixed in the superblock, which checked it against the options
+        let segment_blocks = file_manager.superblock().log_segment_blocks();
+        let segments = LogSegments::open(file_manager, log_file, segment_blocks)?;
+        let mut log_page = Page::new(segments.block_size());
+        let log_size = segments.length()?;
+        let mut discarded_bytes = 0;
         let current_block = {
-            if log_size == 0 {
-                match _append_new_block(&file_manager, log_file, &mut log_page) {
+            if log_size == 0 && options.read_only {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("there is no log {} to open read-only", log_file),
+                ));
+            } else if log_size == 0 {
+                match _append_new_block(&segments, &mut log_page) {
                     Ok(block_id) => block_id,
                     Err(e) => return Err(e),
                 }
             } else {
-                let current_block = BlockId::new(log_file, log_size - 1);
-                file_manager.read(&current_block, &mut log_page)?;
+                let (current_block, discarded) = recover_tail(
+                    &segments,
+                    log_file,
+                    log_size,
+                    &mut log_page,
+                    options.read_only,
+                )?;
+                discarded_bytes = discarded;
                 current_block
             }
         };
+        // everything in the log is on disk already, new records continue after the last one
+        let latest_lsn = lsn_at(segments.block_size(), &current_block, log_page.get_int(0));
         Ok(Self {
-            file_manager,
+            segments: Arc::new(segments),
             log_file: String::from(log_file),
-            log_page,
-            current_block,
-            latest_lsn: 0,
-            latest_saved_lsn: 0,
+            archive: options.log_archive.clone(),
+            retention_lsn: AtomicU64::new(0),
+            state: Mutex::new(LogState {
+                log_page,
+                current_block,
+                filled: VecDeque::new(),
+                latest_lsn,
+                flushing: false,
+            }),
+            flush_done: Condvar::new(),
+            latest_saved_lsn: Arc::new(AtomicU64::new(latest_lsn)),
+            discarded_bytes,
         })
     }
 
     /**
      * flushes the log to disk, ensuring lsn records are persisted to disk.
+     * if another thread is flushing, waits for it first, as it may cover lsn.
      * @param lsn the log sequence number that must be written to disk
      */
-    pub fn flush(&mut self, lsn: u32) -> Result<(), Error> {
-        if lsn >= self.latest_saved_lsn {
-            self._flush()?;
+    pub fn flush(&self, lsn: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        // nothing past the latest record can be waited for
+        let lsn = lsn.min(state.latest_lsn);
+        while lsn > self.get_last_saved_lsn() {
+            state = self.write_out(state)?;
         }
         Ok(())
     }
@@ -55,65 +136,378 @@ impl LogManager {
      * returns an iterator for the log records,
      * which will be returned in reverse order starting with the most recent.
      */
-    pub fn iterator(&mut self) -> Result<LogIterator, Error> {
-        self._flush()?;
-        LogIterator::new(self.file_manager.clone(), &self.current_block)
+    pub fn iterator(&self) -> Result<LogIterator, Error> {
+        self.flush(self.latest_lsn())?;
+        let state = self.state.lock().unwrap();
+        LogIterator::new(self.segments.clone(), &state.current_block, &state.log_page)
+    }
+
+    /**
+     * returns an iterator for the log records from the first one whose lsn is at least lsn,
+     * in the order they were appended. an lsn of 0 starts at the beginning of the log.
+     */
+    pub fn forward_iterator(&self, lsn: u64) -> Result<ForwardLogIterator, Error> {
+        self.flush(self.latest_lsn())?;
+        ForwardLogIterator::new(
+            self.segments.clone(),
+            &self.log_file,
+            self.latest_saved_lsn.clone(),
+            lsn,
+        )
+    }
+
+    // returns an iterator for the log records from the start of the block on
+    pub fn forward_iterator_from_block(&self, blk_num: u64) -> Result<ForwardLogIterator, Error> {
+        // every record of the block has a larger lsn than this, every earlier record a smaller one
+        self.forward_iterator(blk_num * self.segments.block_size() as u64 + 1)
+    }
+
+    /**
+     * returns the record with the given lsn.
+     * fails with InvalidInput if no record has that lsn.
+     */
+    pub fn read_at(&self, lsn: u64) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .record_fragments(lsn)?
+            .into_iter()
+            .flat_map(|(_, f)| f.bytes)
+            .collect())
+    }
+
+    // the fragments of the record with the given lsn, each with the number of its block
+    fn record_fragments(&self, lsn: u64) -> Result<Vec<(u64, Fragment)>, Error> {
+        let block_size = self.segments.block_size() as u64;
+        let mut blk_num = lsn.saturating_sub(1) / block_size;
+        let first = match lsn {
+            0 => None,
+            _ => self.fragments(blk_num)?.into_iter().find(|f| {
+                f.lsn == lsn && matches!(f.kind, FragmentKind::Full | FragmentKind::First)
+            }),
+        };
+        let first = first.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("no log record has lsn {}", lsn),
+            )
+        })?;
+        let mut kind = first.kind;
+        let mut fragments = vec![(blk_num, first)];
+        // the rest of a split record is at the end of the following blocks, where they start
+        while kind != FragmentKind::Full && kind != FragmentKind::Last {
+            blk_num += 1;
+            let fragment = self.fragments(blk_num)?.pop();
+            match fragment {
+                Some(f) if matches!(f.kind, FragmentKind::Middle | FragmentKind::Last) => {
+                    kind = f.kind;
+                    fragments.push((blk_num, f));
+                }
+                _ => return Err(corrupted(&BlockId::new(&self.log_file, blk_num), 0)),
+            }
+        }
+        Ok(fragments)
+    }
+
+    // fragments of a block of the log, the newest first; blocks not written yet are taken from memory
+    fn fragments(&self, blk_num: u64) -> Result<Vec<Fragment>, Error> {
+        let block_size = self.segments.block_size();
+        let block_id = BlockId::new(&self.log_file, blk_num);
+        {
+            let state = self.state.lock().unwrap();
+            if block_id == state.current_block {
+                return read_fragments(&state.log_page, &block_id, block_size);
+            }
+            if let Some((_, page)) = state.filled.iter().find(|(b, _)| *b == block_id) {
+                return read_fragments(page, &block_id, block_size);
+            }
+            if blk_num > state.current_block.number() || blk_num < self.segments.first_block() {
+                return Ok(Vec::new());
+            }
+        }
+        // blocks leave memory only once they are written
+        let mut page = Page::new(block_size);
+        self.segments.read(&block_id, &mut page)?;
+        read_fragments(&page, &block_id, block_size)
     }
 
     /**
      * add a record to the log and returns its log sequence number.
      * appeding a record to log does not guarantee that it is immediately written to disk.
+     * a record too large for one block is split over as many as it needs.
      * @param log_record the log record to be added
      */
-    pub fn append(&mut self, log_record: &Vec<u8>) -> Result<u32, Error> {
-        // boundary: offset of the most recently added log record
-        let mut boundary = self.log_page.get_int(0) as i32;
-        let bytes_needed = (log_record.len() + size_of::<u32>()) as i32;
-
-        if (boundary - bytes_needed) < (size_of::<u32>() as i32) {
-            // it doesn't fit in the current block so move to the next one
-            self._flush()?;
-            self.current_block = self.append_new_block()?;
-            boundary = self.log_page.get_int(0) as i32;
+    pub fn append(&self, log_record: &Vec<u8>) -> Result<u64, Error> {
+        let block_size = self.segments.block_size();
+        // most bytes of a record an empty block holds
+        let capacity = block_size - size_of::<u32>() - FRAGMENT_HEADER_SIZE;
+        let mut state = self.state.lock().unwrap();
+        // the record is appended in one go, so the blocks it may fill have to be free
+        let blocks = log_record.len() / capacity + 1;
+        while !state.filled.is_empty() && state.filled.len() + blocks > LOG_BUFFERS {
+            state = self.write_out(state)?;
+        }
+        if log_record.len() <= capacity && !fits(&state.log_page, log_record.len()) {
+            // it doesn't fit in the current block but in the next one, so it is not split
+            self.move_to_new_block(&mut state)?;
         }
 
-        let rec_offset = boundary - bytes_needed;
-        self.log_page.set_bytes(rec_offset as usize, log_record);
-        self.log_page.set_int(0, rec_offset as u32);
+        let mut lsn = None;
+        let mut written = 0;
+        loop {
+            // a fragment holds at least one byte, unless the record is empty
+            if !fits(&state.log_page, (log_record.len() - written).min(1)) {
+                self.move_to_new_block(&mut state)?;
+                continue;
+            }
+            let len = room(&state.log_page).min(log_record.len() - written);
+            let is_last = written + len == log_record.len();
+            let kind = match (written == 0, is_last) {
+                (true, true) => FragmentKind::Full,
+                (true, false) => FragmentKind::First,
+                (false, false) => FragmentKind::Middle,
+                (false, true) => FragmentKind::Last,
+            };
+            let state = &mut *state;
+            let fragment_lsn = write_fragment(
+                &mut state.log_page,
+                &state.current_block,
+                block_size,
+                kind,
+                &log_record[written..written + len],
+            );
+            lsn.get_or_insert(fragment_lsn);
+            written += len;
+            if is_last {
+                break;
+            }
+            self.move_to_new_block(state)?;
+        }
 
-        self.latest_lsn += 1;
-        Ok(self.latest_lsn)
+        state.latest_lsn = lsn.unwrap();
+        Ok(state.latest_lsn)
     }
 
-    pub fn get_last_saved_lsn(&self) -> u32 {
-        self.latest_saved_lsn
+    /**
+     * cuts the log off after the record with the given lsn, e.g. where point-in-time recovery
+     * stopped, so that the records past it are gone for good. records appended later follow it.
+     * an lsn of 0 empties the log. fails with InvalidInput if no record has the lsn.
+     */
+    pub fn truncate_after(&self, lsn: u64) -> Result<(), Error> {
+        let block_size = self.segments.block_size();
+        let (end_block, end_offset) = match lsn {
+            0 => (self.segments.first_block(), block_size),
+            _ => {
+                let (blk_num, last) = self.record_fragments(lsn)?.pop().unwrap();
+                (blk_num, last.offset)
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        // the blocks are cut on disk, so everything has to be there first
+        while state.flushing || state.latest_lsn > self.get_last_saved_lsn() {
+            state = self.write_out(state)?;
+        }
+        let end_block = BlockId::new(&self.log_file, end_block);
+        let mut page = Page::new(block_size);
+        self.segments.read(&end_block, &mut page)?;
+        cut_block(&mut page, end_offset);
+        self.segments.write(&end_block, &mut page)?;
+        self.segments.truncate_to(end_block.number() + 1)?;
+        self.segments.sync(std::slice::from_ref(&end_block))?;
+
+        let latest_lsn = lsn_at(block_size, &end_block, end_offset as u32);
+        state.log_page = page;
+        state.current_block = end_block;
+        state.latest_lsn = latest_lsn;
+        self.latest_saved_lsn.store(latest_lsn, Ordering::Release);
+        Ok(())
     }
 
-    fn append_new_block(&mut self) -> Result<BlockId, Error> {
-        _append_new_block(
-            &self.file_manager,
-            &self.log_file.clone(),
-            &mut self.log_page,
-        )
+    // lsn of the most recently appended record
+    pub fn latest_lsn(&self) -> u64 {
+        self.state.lock().unwrap().latest_lsn
+    }
+
+    pub fn get_last_saved_lsn(&self) -> u64 {
+        self.latest_saved_lsn.load(Ordering::Acquire)
+    }
+
+    // bytes of a torn tail that were thrown away when the log was opened
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded_bytes
+    }
+
+    /**
+     * declares the lsn of the oldest record still needed, e.g. by recovery after a checkpoint.
+     * the segments before it are let go as soon as they are on disk: moved to the archive if
+     * there is one, deleted otherwise. the retention lsn never goes back.
+     */
+    pub fn set_retention_lsn(&self, lsn: u64) -> Result<(), Error> {
+        self.retention_lsn.fetch_max(lsn, Ordering::AcqRel);
+        self.release_segments()
+    }
+
+    pub fn retention_lsn(&self) -> u64 {
+        self.retention_lsn.load(Ordering::Acquire)
+    }
+
+    // names of the segment files the log is kept in, the oldest first
+    pub fn segment_names(&self) -> Vec<String> {
+        self.segments.segment_names()
+    }
+
+    // lets go of the segments that are on disk and hold only records older than the retention lsn
+    fn release_segments(&self) -> Result<(), Error> {
+        let keep = self.retention_lsn().min(self.get_last_saved_lsn());
+        if keep == 0 {
+            return Ok(());
+        }
+        let block = (keep - 1) / self.segments.block_size() as u64;
+        self.segments.release(block, self.archive.as_deref())?;
+        Ok(())
+    }
+
+    // queues the current block to be written and continues in a new one, which is added to the
+    // log file only when it is written out
+    fn move_to_new_block(&self, state: &mut LogState) -> Result<(), Error> {
+        let block_size = self.segments.block_size();
+        let mut page = Page::new(block_size);
+        page.set_int(0, block_size as u32);
+        let block_id = BlockId::new(&self.log_file, state.current_block.number() + 1);
+        let filled_page = std::mem::replace(&mut state.log_page, page);
+        let filled_block = std::mem::replace(&mut state.current_block, block_id);
+        state.filled.push_back((filled_block, filled_page));
+        Ok(())
     }
 
-    fn _flush(&mut self) -> Result<(), Error> {
-        self.file_manager
-            .write(&self.current_block, &mut self.log_page)?;
-        self.latest_saved_lsn = self.latest_lsn;
+    // adds blocks to the end of the log file until it holds the block, only done by the flushing thread
+    fn extend_to(&self, block_id: &BlockId) -> Result<(), Error> {
+        for _ in self.segments.length()?..=block_id.number() {
+            self.segments.append()?;
+        }
         Ok(())
     }
+
+    /**
+     * writes the queued blocks and a copy of the current one and syncs the log, or waits for the
+     * thread doing so. the lock is released meanwhile and held again when it returns.
+     */
+    fn write_out<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, LogState>,
+    ) -> Result<MutexGuard<'a, LogState>, Error> {
+        if state.flushing {
+            return Ok(self.flush_done.wait(state).unwrap());
+        }
+        state.flushing = true;
+        let latest_lsn = state.latest_lsn;
+        let queued = state.filled.len();
+        let (mut blocks, mut pages): (Vec<_>, Vec<_>) = state
+            .filled
+            .iter()
+            .map(|(block_id, page)| (block_id.clone(), Page::from_bytes(page.contents())))
+            .unzip();
+        blocks.push(state.current_block.clone());
+        pages.push(Page::from_bytes(state.log_page.contents()));
+        drop(state);
+
+        // the queued blocks and the current one follow each other, so they go out in one write
+        let result = self
+            .extend_to(blocks.last().unwrap())
+            .and_then(|_| self.segments.write_many(&blocks, &pages))
+            .and_then(|_| self.segments.sync(&blocks));
+
+        let mut state = self.state.lock().unwrap();
+        state.flushing = false;
+        self.flush_done.notify_all();
+        result?;
+        state.filled.drain(..queued);
+        self.latest_saved_lsn
+            .fetch_max(latest_lsn, Ordering::AcqRel);
+        drop(state);
+        // segments may have become old enough to go now that more of the log is on disk
+        self.release_segments()?;
+        Ok(self.state.lock().unwrap())
+    }
 }
 
-fn _append_new_block(
-    file_manager: &FileManager,
+/**
+ * finds the end of the last complete record in the log and cuts off whatever follows it: the
+ * fragments a torn write of the last block damaged, and the fragments of a record whose last
+ * fragment never made it to disk. loads the block the log now ends in into log_page and returns
+ * it together with the number of bytes discarded.
+ * a read-only log is left as it is on disk, the end is only cut off in log_page.
+ */
+fn recover_tail(
+    segments: &LogSegments,
     log_file: &str,
+    log_size: u64,
     log_page: &mut Page,
-) -> Result<BlockId, Error> {
-    let block_id = file_manager.append(log_file)?;
+    read_only: bool,
+) -> Result<(BlockId, u64), Error> {
+    let block_size = segments.block_size();
+    let last_block = BlockId::new(log_file, log_size - 1);
+    // bytes in use in the blocks looked at, from their boundary to their end
+    let mut used = 0;
+    let mut intact = true;
+    // going back through an unfinished record, looking for its first fragment
+    let mut in_unfinished = false;
+    let mut blk_num = log_size - 1;
+    let (end_block, end_offset) = loop {
+        let block_id = BlockId::new(log_file, blk_num);
+        intact &= segments.read_salvaged(&block_id, log_page)?;
+        let boundary = log_page.get_int(0) as usize;
+        let from = match boundary {
+            // never written, or written as a new block with nothing in it yet
+            0 => block_size,
+            b if (size_of::<u32>()..=block_size).contains(&b) => b,
+            _ => size_of::<u32>(),
+        };
+        used += (block_size - from) as u64;
+        let (_, fragments) = intact_fragments(log_page, &block_id, block_size, from);
+        let mut end = None;
+        for fragment in fragments {
+            match fragment.kind {
+                _ if in_unfinished => in_unfinished = fragment.kind != FragmentKind::First,
+                FragmentKind::Full | FragmentKind::Last => {
+                    end = Some(fragment.offset);
+                    break;
+                }
+                FragmentKind::Middle => in_unfinished = true,
+                FragmentKind::First => {}
+            }
+        }
+        match end {
+            Some(offset) => break (block_id, offset),
+            None if blk_num == segments.first_block() => break (block_id, block_size),
+            None => blk_num -= 1,
+        }
+    };
+    let discarded = used - (block_size - end_offset) as u64;
+    if discarded > 0 || !intact || end_block != last_block {
+        cut_block(log_page, end_offset);
+        if read_only {
+            return Ok((end_block, discarded));
+        }
+        segments.write(&end_block, log_page)?;
+        segments.truncate_to(end_block.number() + 1)?;
+        segments.sync(std::slice::from_ref(&end_block))?;
+    }
+    Ok((end_block, discarded))
+}
+
+// makes the offset the boundary of a log block and clears what is in front of it, so that it
+// cannot be mistaken for records later
+fn cut_block(log_page: &mut Page, end_offset: usize) {
+    log_page
+        .try_set_fixed_bytes(size_of::<u32>(), &vec![0; end_offset - size_of::<u32>()])
+        .unwrap();
+    log_page.set_int(0, end_offset as u32);
+}
+
+fn _append_new_block(segments: &LogSegments, log_page: &mut Page) -> Result<BlockId, Error> {
+    let block_id = segments.append()?;
     // Use the first four bytes as boundary, which is the offset of the most recently added log record
     // if the block_size is 400, then the boundary is 400 at the beginning
-    log_page.set_int(0, file_manager.block_size() as u32);
-    file_manager.write(&block_id, log_page)?;
+    log_page.set_int(0, segments.block_size() as u32);
+    segments.write(&block_id, log_page)?;
     Ok(block_id)
 }
@@ -0,0 +1,254 @@
+use std::{
+    collections::BTreeSet,
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex},
+};
+
+use crate::file::{block_id::BlockId, file_manager::FileManager, page::Page, stats::FileKind};
+
+// The blocks of the log, numbered from the start of the log, kept in segment files of a fixed
+// number of blocks each, named <log file>.000000, <log file>.000001, ...
+// The zero padding keeps the names apart from the segment files of the filesystem backend.
+// Without a segment size the whole log is kept in the log file itself, as it always was.
+// Segments before the oldest record still needed are removed, or moved to an archive.
+// The BlockIds used for the log name the log file and the number of the block in the whole
+// log; they are mapped to the segment holding the block on every access.
+pub struct LogSegments {
+    file_manager: Arc<FileManager>,
+    log_file: String,
+    // blocks per segment, 0 if the log is a single file
+    segment_blocks: u64,
+    // numbers of the first and the last segment kept
+    range: Mutex<(u64, u64)>,
+    // held while segments are let go, one thread at a time
+    releasing: Mutex<()>,
+}
+
+impl LogSegments {
+    pub fn open(
+        file_manager: Arc<FileManager>,
+        log_file: &str,
+        segment_blocks: u64,
+    ) -> Result<Self, Error> {
+        let numbers: BTreeSet<u64> = file_manager
+            .files()?
+            .iter()
+            .filter_map(|name| segment_number(log_file, name))
+            .collect();
+        if segment_blocks == 0 && !numbers.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is split into segments, a log segment size is needed",
+                    log_file
+                ),
+            ));
+        }
+        if segment_blocks > 0 && file_manager.length(log_file)? > 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is a single file, it cannot be opened as segments",
+                    log_file
+                ),
+            ));
+        }
+        let first = numbers.first().copied().unwrap_or(0);
+        let last = numbers.last().copied().unwrap_or(0);
+        let segments = Self {
+            file_manager,
+            log_file: log_file.to_string(),
+            segment_blocks,
+            range: Mutex::new((first, last)),
+            releasing: Mutex::new(()),
+        };
+        for n in first..=last {
+            let name = segments.segment_name(n);
+            segments.file_manager.set_file_kind(&name, FileKind::Log);
+            let length = segments.file_manager.length(&name)?;
+            // every segment but the last one is full, and none is missing
+            if segment_blocks > 0 && n < last && length != segment_blocks {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "log segment {} has {} blocks instead of {}",
+                        name, length, segment_blocks
+                    ),
+                ));
+            }
+        }
+        Ok(segments)
+    }
+
+    /**
+     * copies the segments archived to the location that come before the first segment still in
+     * the database back into it, so that the log reaches back as far as the archive does, e.g.
+     * to replay it over a restored backup. returns their names. done before the log is opened.
+     */
+    pub fn restore_archived(
+        file_manager: &FileManager,
+        log_file: &str,
+        location: &str,
+    ) -> Result<Vec<String>, Error> {
+        let first_kept = file_manager
+            .files()?
+            .iter()
+            .filter_map(|name| segment_number(log_file, name))
+            .min();
+        let mut archived: Vec<(u64, String)> = file_manager
+            .archived_files(location)?
+            .into_iter()
+            .filter_map(|name| Some((segment_number(log_file, &name)?, name)))
+            .filter(|(n, _)| first_kept.is_none_or(|first| *n < first))
+            .collect();
+        archived.sort();
+        for (_, name) in &archived {
+            file_manager.restore_file(name, location)?;
+        }
+        Ok(archived.into_iter().map(|(_, name)| name).collect())
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.file_manager.block_size()
+    }
+
+    // name of the file holding segment n
+    pub fn segment_name(&self, n: u64) -> String {
+        match self.segment_blocks {
+            0 => self.log_file.clone(),
+            _ => format!("{}.{:06}", self.log_file, n),
+        }
+    }
+
+    // names of the segment files kept, the oldest first
+    pub fn segment_names(&self) -> Vec<String> {
+        let (first, last) = *self.range.lock().unwrap();
+        (first..=last).map(|n| self.segment_name(n)).collect()
+    }
+
+    // the block of a segment file that holds the block of the log
+    fn physical(&self, blk: &BlockId) -> BlockId {
+        match self.segment_blocks {
+            0 => blk.clone(),
+            n => BlockId::new(&self.segment_name(blk.number() / n), blk.number() % n),
+        }
+    }
+
+    // number of blocks of the log, counting those of segments that were removed
+    pub fn length(&self) -> Result<u64, Error> {
+        let (_, last) = *self.range.lock().unwrap();
+        let length = self.file_manager.length(&self.segment_name(last))?;
+        Ok(last * self.segment_blocks + length)
+    }
+
+    // the first block of the log that was not removed
+    pub fn first_block(&self) -> u64 {
+        self.range.lock().unwrap().0 * self.segment_blocks
+    }
+
+    pub fn read(&self, blk: &BlockId, page: &mut Page) -> Result<(), Error> {
+        self.file_manager.read(&self.physical(blk), page)
+    }
+
+    pub fn read_salvaged(&self, blk: &BlockId, page: &mut Page) -> Result<bool, Error> {
+        self.file_manager.read_salvaged(&self.physical(blk), page)
+    }
+
+    pub fn write(&self, blk: &BlockId, page: &mut Page) -> Result<(), Error> {
+        self.file_manager.write(&self.physical(blk), page)
+    }
+
+    pub fn write_many(&self, blks: &[BlockId], pages: &[Page]) -> Result<(), Error> {
+        let blks: Vec<BlockId> = blks.iter().map(|blk| self.physical(blk)).collect();
+        self.file_manager.write_many(&blks, pages)
+    }
+
+    // syncs the segments holding the blocks
+    pub fn sync(&self, blks: &[BlockId]) -> Result<(), Error> {
+        let names: BTreeSet<String> = blks
+            .iter()
+            .map(|blk| self.physical(blk).filename().to_string())
+            .collect();
+        for name in names {
+            self.file_manager.sync(&name)?;
+        }
+        Ok(())
+    }
+
+    // adds an empty block to the end of the log, starting a new segment when the last one is full
+    pub fn append(&self) -> Result<BlockId, Error> {
+        let mut range = self.range.lock().unwrap();
+        if self.segment_blocks > 0
+            && self.file_manager.length(&self.segment_name(range.1))? >= self.segment_blocks
+        {
+            range.1 += 1;
+            self.file_manager
+                .set_file_kind(&self.segment_name(range.1), FileKind::Log);
+        }
+        let blk = self.file_manager.append(&self.segment_name(range.1))?;
+        Ok(BlockId::new(
+            &self.log_file,
+            range.1 * self.segment_blocks + blk.number(),
+        ))
+    }
+
+    // cuts the log off after its first length blocks, removing the segments past that
+    pub fn truncate_to(&self, length: u64) -> Result<(), Error> {
+        let mut range = self.range.lock().unwrap();
+        if self.segment_blocks == 0 {
+            return self.file_manager.truncate_to(&self.log_file, length);
+        }
+        let last = length.saturating_sub(1) / self.segment_blocks;
+        while range.1 > last.max(range.0) {
+            self.file_manager.remove_file(&self.segment_name(range.1))?;
+            range.1 -= 1;
+        }
+        self.file_manager.truncate_to(
+            &self.segment_name(range.1),
+            length.saturating_sub(range.1 * self.segment_blocks),
+        )
+    }
+
+    /**
+     * removes the segments that end at or before the block, except the last one, or moves them
+     * to the archive location if there is one. returns the names of the segments let go.
+     */
+    pub fn release(&self, before_block: u64, archive: Option<&str>) -> Result<Vec<String>, Error> {
+        let mut released = Vec::new();
+        if self.segment_blocks == 0 {
+            return Ok(released);
+        }
+        let _releasing = self.releasing.lock().unwrap();
+        loop {
+            // the segment is taken out of the range first so that readers no longer look at it,
+            // and the range is not locked while the file is copied, so appends carry on
+            let first = {
+                let mut range = self.range.lock().unwrap();
+                if range.0 >= range.1 || (range.0 + 1) * self.segment_blocks > before_block {
+                    return Ok(released);
+                }
+                range.0 += 1;
+                range.0 - 1
+            };
+            let name = self.segment_name(first);
+            let result = match archive {
+                Some(location) => self.file_manager.archive_file(&name, location),
+                None => self.file_manager.remove_file(&name),
+            };
+            if let Err(e) = result {
+                self.range.lock().unwrap().0 = first;
+                return Err(e);
+            }
+            released.push(name);
+        }
+    }
+}
+
+// number of the segment of the log the file is, None if it is not one
+fn segment_number(log_file: &str, name: &str) -> Option<u64> {
+    let n = name.strip_prefix(log_file)?.strip_prefix('.')?;
+    if n.len() < 6 || !n.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    n.parse().ok()
+}
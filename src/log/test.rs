@@ -2,33 +2,572 @@
 mod tests {
     use std::{
         fs,
-        iter::zip,
+        io::ErrorKind,
         mem::size_of,
-        sync::{Arc, Mutex},
+        sync::{Arc, Barrier},
+        thread,
+        time::Duration,
     };
 
-    use crate::{app::simple_db::SimpleDB, file::page::Page, log::log_manager::LogManager};
+    use crate::{
+        app::simple_db::SimpleDB,
+        file::{
+            block_id::BlockId,
+            file_manager::SyncStats,
+            options::{Durability, FileOptions},
+            page::Page,
+            storage::{
+                faulty_backend::{Fault, FaultyBackend},
+                memory_backend::MemoryBackend,
+                StorageBackend,
+            },
+            superblock::SuperblockError,
+        },
+        log::log_manager::LogManager,
+    };
 
     #[test]
     fn test_log() {
-        let db = SimpleDB::new("./test_log/log", 400, 8).unwrap();
+        let db = SimpleDB::new_in_memory(400, 8).unwrap();
         let lm = db.log_manager();
         const NUM: u32 = 350;
-        create_records(lm.clone(), 1, NUM);
+        let lsns = create_records(lm.clone(), 1, NUM);
         assert_log_records(lm.clone(), (1..=NUM).rev().collect());
 
         create_records(lm.clone(), NUM + 1, NUM * 2);
-        lm.lock().unwrap().flush(NUM - 10).unwrap();
+        lm.flush(lsns[NUM as usize - 10]).unwrap();
         assert_log_records(lm.clone(), (1..=NUM * 2).rev().collect());
+    }
+
+    #[test]
+    fn test_log_reopen() {
+        const NUM: u32 = 100;
+        let storage = Arc::new(MemoryBackend::new());
+        let last_lsn;
+        {
+            let db = SimpleDB::with_backend(storage.clone(), 400, 8).unwrap();
+            let lm = db.log_manager();
+            let lsns = create_records(lm.clone(), 1, NUM);
+            lm.flush(lsns[lsns.len() - 1]).unwrap();
+            last_lsn = lsns[lsns.len() - 1];
+        }
+
+        let db = SimpleDB::with_backend(storage, 400, 8).unwrap();
+        assert!(!db.file_manager().is_new());
+        assert_log_records(db.log_manager(), (1..=NUM).rev().collect());
+
+        // lsns carry on from where the log ended instead of starting over
+        let lm = db.log_manager();
+        assert_eq!(lm.get_last_saved_lsn(), last_lsn);
+        let lsns = create_records(lm.clone(), NUM + 1, NUM + 10);
+        assert!(lsns[0] > last_lsn);
+        assert_log_records(lm, (1..=NUM + 10).rev().collect());
+    }
+
+    #[test]
+    fn test_crash_between_log_flush_and_data_write() {
+        let storage = Arc::new(MemoryBackend::new());
+        let faulty = Arc::new(FaultyBackend::new(storage.clone()));
+        let blk = BlockId::new("data", 0);
+        {
+            let db = SimpleDB::with_backend(faulty.clone(), 400, 8).unwrap();
+            let fm = db.file_manager();
+            let lm = db.log_manager();
+            let lsns = create_records(lm.clone(), 1, 10);
+            lm.flush(lsns[9]).unwrap();
+
+            // the process dies while writing the data page the records describe
+            faulty.fail_nth_write(1);
+            let mut page = Page::new(fm.block_size());
+            page.set_int(0, 110);
+            assert!(fm.write(&blk, &mut page).is_err());
+            faulty.crash();
+        }
+
+        let db = SimpleDB::with_backend(storage, 400, 8).unwrap();
+        assert_log_records(db.log_manager(), (1..=10).rev().collect());
+        let mut page = Page::new(400);
+        db.file_manager().read(&blk, &mut page).unwrap();
+        assert_eq!(page.get_int(0), 0);
+    }
+
+    #[test]
+    fn test_durability() {
+        for durability in [Durability::None, Durability::DataSync, Durability::FullSync] {
+            let storage = Arc::new(MemoryBackend::new());
+            let faulty = Arc::new(FaultyBackend::new(storage.clone()));
+            {
+                let options = FileOptions {
+                    durability,
+                    ..FileOptions::default()
+                };
+                let db = SimpleDB::open(faulty.clone(), 400, 8, options).unwrap();
+                let lm = db.log_manager();
+                let lsns = create_records(lm.clone(), 1, 10);
+                lm.flush(lsns[9]).unwrap();
+                // the flush syncs the log, the first sync of the new log file also syncs the directory
+                let expected = match durability {
+                    Durability::None => SyncStats::default(),
+                    Durability::DataSync => SyncStats {
+                        data_syncs: 1,
+                        full_syncs: 0,
+                        directory_syncs: 1,
+                    },
+                    Durability::FullSync => SyncStats {
+                        data_syncs: 0,
+                        full_syncs: 1,
+                        directory_syncs: 1,
+                    },
+                };
+                assert_eq!(db.file_manager().sync_stats(), expected);
+                faulty.crash();
+            }
+
+            let db = SimpleDB::with_backend(storage, 400, 8).unwrap();
+            let survived = db.log_manager().iterator().unwrap().count();
+            // without syncing, the flushed records were still in the operating system cache
+            if durability == Durability::None {
+                assert_eq!(survived, 0);
+            } else {
+                assert_eq!(survived, 10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_forward_iterator() {
+        let db = SimpleDB::new_in_memory(400, 8).unwrap();
+        const NUM: u32 = 100;
+        let lsns = create_records(db.log_manager(), 1, NUM);
+
+        let lm = db.log_manager();
+        let records: Vec<(u64, Vec<u8>)> = lm
+            .forward_iterator(0)
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(records.len(), NUM as usize);
+        for (i, (lsn, rec)) in records.iter().enumerate() {
+            assert_eq!(*lsn, lsns[i]);
+            assert_eq!(record_number(rec), i as u32 + 1);
+        }
+        // the backward iterator returns the same records in reverse
+        let backward: Vec<(u64, Vec<u8>)> = lm.iterator().unwrap().map(|r| r.unwrap()).collect();
+        assert!(backward.into_iter().rev().eq(records.iter().cloned()));
+
+        // starting at an lsn, or at one past it
+        let mut itr = lm.forward_iterator(lsns[41]).unwrap();
+        assert_eq!(itr.next().unwrap().unwrap().0, lsns[41]);
+        let mut itr = lm.forward_iterator(lsns[41] + 1).unwrap();
+        assert_eq!(itr.next().unwrap().unwrap().0, lsns[42]);
+        assert!(lm
+            .forward_iterator(lsns[NUM as usize - 1] + 1)
+            .unwrap()
+            .next()
+            .is_none());
+
+        // starting at a block returns its first record first
+        let blk = lsns[60] / 400;
+        let first = lsns.iter().position(|lsn| lsn / 400 == blk).unwrap();
+        let mut itr = lm.forward_iterator_from_block(blk).unwrap();
+        assert_eq!(itr.next().unwrap().unwrap().0, lsns[first]);
+
+        // records appended after the iterator was made are returned once flushed
+        let mut itr = lm.forward_iterator(lsns[NUM as usize - 1]).unwrap();
+        assert!(itr.next().is_some());
+        assert!(itr.next().is_none());
+        let lsns_after: Vec<u64> = (NUM + 1..=NUM + 20)
+            .map(|i| {
+                let rec = create_log_records(&format!("record: {}", i), i + 100);
+                lm.append(&rec).unwrap()
+            })
+            .collect();
+        lm.flush(lsns_after[19]).unwrap();
+        let rest: Vec<u64> = itr.map(|r| r.unwrap().0).collect();
+        assert_eq!(rest, lsns_after);
+
+        // random access, including to records that are still in memory
+        for i in [0, 17, NUM as usize - 1] {
+            assert_eq!(record_number(&lm.read_at(lsns[i]).unwrap()), i as u32 + 1);
+        }
+        let lsn = lm.append(&create_log_records("record: 7", 107)).unwrap();
+        assert_eq!(record_number(&lm.read_at(lsn).unwrap()), 7);
+        for lsn in [0, lsns[3] + 1, lsn + 1, u64::MAX] {
+            let err = lm.read_at(lsn).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_iterator_errors() {
+        let db = SimpleDB::new_in_memory(400, 8).unwrap();
+        let lm = db.log_manager();
+        create_records(lm.clone(), 1, 50);
+
+        // a boundary pointing outside the block cannot be parsed
+        let fm = db.file_manager();
+        let mut page = Page::new(400);
+        page.set_int(0, 1000);
+        fm.write(&BlockId::new("simpledb.log", 0), &mut page)
+            .unwrap();
+
+        let results: Vec<_> = lm.iterator().unwrap().collect();
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(results[..results.len() - 1].iter().all(|r| r.is_ok()));
+        assert!(lm.forward_iterator(0).is_err());
+        let mut itr = lm.forward_iterator_from_block(1).unwrap();
+        assert!(itr.next().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_large_records() {
+        let storage = Arc::new(MemoryBackend::new());
+        // an empty 400 byte block holds 380 bytes of a record
+        let sizes = [10, 1000, 5000, 0, 380, 381, 379, 20, 2000, 7];
+        let records: Vec<Vec<u8>> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, len)| (0..*len).map(|j| (i * 31 + j) as u8).collect())
+            .collect();
+        let mut lsns = Vec::new();
+        {
+            let db = SimpleDB::with_backend(storage.clone(), 400, 8).unwrap();
+            let lm = db.log_manager();
+            for rec in &records {
+                let lsn = lm.append(rec).unwrap();
+                assert!(lsns.last().is_none_or(|last| lsn > *last));
+                lsns.push(lsn);
+            }
+
+            // the records are put back together going either way, and looked up by lsn
+            let backward: Vec<(u64, Vec<u8>)> =
+                lm.iterator().unwrap().map(|r| r.unwrap()).collect();
+            let expected: Vec<(u64, Vec<u8>)> =
+                lsns.iter().cloned().zip(records.iter().cloned()).collect();
+            assert!(backward.into_iter().rev().eq(expected.iter().cloned()));
+            let forward: Vec<(u64, Vec<u8>)> = lm
+                .forward_iterator(0)
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(forward, expected);
+            for (lsn, rec) in &expected {
+                assert_eq!(&lm.read_at(*lsn).unwrap(), rec);
+            }
+
+            // starting in the middle of a split record skips it
+            let mut itr = lm.forward_iterator(lsns[2]).unwrap();
+            assert_eq!(itr.next().unwrap().unwrap().0, lsns[2]);
+            let mut itr = lm.forward_iterator(lsns[2] + 1).unwrap();
+            assert_eq!(itr.next().unwrap().unwrap().0, lsns[3]);
+            let mut itr = lm.forward_iterator_from_block(lsns[2] / 400 + 5).unwrap();
+            assert_eq!(itr.next().unwrap().unwrap().0, lsns[3]);
+            lm.flush(lsns[lsns.len() - 1]).unwrap();
+        }
+
+        let db = SimpleDB::with_backend(storage, 400, 8).unwrap();
+        let lm = db.log_manager();
+        let forward: Vec<Vec<u8>> = lm
+            .forward_iterator(0)
+            .unwrap()
+            .map(|r| r.unwrap().1)
+            .collect();
+        assert_eq!(forward, records);
+        assert_eq!(lm.read_at(lsns[8]).unwrap(), records[8]);
+    }
+
+    #[test]
+    fn test_unfinished_record() {
+        let faulty = Arc::new(FaultyBackend::new(Arc::new(MemoryBackend::new())));
+        {
+            let db = SimpleDB::with_backend(faulty.clone(), 400, 8).unwrap();
+            let lm = db.log_manager();
+            let lsn = lm.append(&b"before".to_vec()).unwrap();
+            lm.flush(lsn).unwrap();
+            // the record fills five blocks, the flush adds the four new ones to the log file,
+            // writes the first four of the five and dies
+            let writes = faulty.writes();
+            let lsn = lm.append(&vec![1; 1500]).unwrap();
+            // appending leaves the log file alone, even when the record moves on to new blocks
+            assert_eq!(faulty.writes(), writes);
+            faulty.fail_nth_write(9);
+            assert!(lm.flush(lsn).is_err());
+        }
+
+        let db = SimpleDB::with_backend(faulty, 400, 8).unwrap();
+        let lm = db.log_manager();
+        // the blocks holding the start of the record are cut off
+        assert!(lm.discarded_bytes() > 1000);
+        assert_eq!(db.file_manager().length("simpledb.log").unwrap(), 1);
+        let after = lm.append(&b"after".to_vec()).unwrap();
+        lm.flush(after).unwrap();
+        let backward: Vec<Vec<u8>> = lm.iterator().unwrap().map(|r| r.unwrap().1).collect();
+        assert_eq!(backward, vec![b"after".to_vec(), b"before".to_vec()]);
+        let forward: Vec<Vec<u8>> = lm
+            .forward_iterator(0)
+            .unwrap()
+            .map(|r| r.unwrap().1)
+            .collect();
+        assert_eq!(forward, vec![b"before".to_vec(), b"after".to_vec()]);
+    }
+
+    #[test]
+    fn test_torn_log_tail() {
+        // log blocks are rewritten in place in a compressed database too
+        for compression in [false, true] {
+            let options = FileOptions {
+                compression,
+                ..FileOptions::default()
+            };
+            let storage = Arc::new(MemoryBackend::new());
+            let faulty = Arc::new(FaultyBackend::new(storage.clone()));
+            let lsns;
+            {
+                let db = SimpleDB::open(faulty.clone(), 400, 8, options.clone()).unwrap();
+                let lm = db.log_manager();
+                let mut flushed = create_records(lm.clone(), 1, 5);
+                lm.flush(flushed[4]).unwrap();
+                flushed.extend(create_records(lm.clone(), 6, 8));
+                lsns = flushed;
+                // the flush of the last three records rewrites the block, but only its header and
+                // boundary reach the disk
+                faulty.tear_nth_write(1, 24);
+                lm.flush(lsns[7]).unwrap();
+                faulty.crash();
+            }
+
+            let db = SimpleDB::open(storage.clone(), 400, 8, options.clone()).unwrap();
+            let lm = db.log_manager();
+            assert_eq!(lm.discarded_bytes(), lsns[7] - lsns[4]);
+            assert_eq!(lm.get_last_saved_lsn(), lsns[4]);
+            assert_log_records(lm.clone(), (1..=5).rev().collect());
+            // the next record takes the place of the first lost one
+            let lsn = create_records(lm.clone(), 6, 6)[0];
+            assert_eq!(lsn, lsns[5]);
+            lm.flush(lsn).unwrap();
+            drop(db);
+
+            // the log is whole again
+            let db = SimpleDB::open(storage, 400, 8, options).unwrap();
+            let lm = db.log_manager();
+            assert_eq!(lm.discarded_bytes(), 0);
+            assert_log_records(lm, (1..=6).rev().collect());
+        }
+    }
+
+    #[test]
+    fn test_append_during_flush() {
+        let faulty = Arc::new(FaultyBackend::new(Arc::new(MemoryBackend::new())));
+        let db = SimpleDB::with_backend(faulty.clone(), 400, 8).unwrap();
+        let lm = db.log_manager();
+        let lsns = create_records(lm.clone(), 1, 5);
+
+        // the flush takes a while to write the log
+        faulty.inject(1, Fault::Delay(Duration::from_millis(500)));
+        let flush = {
+            let lm = lm.clone();
+            let lsn = lsns[4];
+            thread::spawn(move || lm.flush(lsn))
+        };
+        thread::sleep(Duration::from_millis(50));
+        // appends carry on meanwhile, filling new blocks
+        let more = create_records(lm.clone(), 6, 30);
+        assert!(more[24] / 400 > lsns[4] / 400);
+        assert!(!flush.is_finished());
+        flush.join().unwrap().unwrap();
+        assert!(lm.get_last_saved_lsn() >= lsns[4]);
+        assert!(lm.get_last_saved_lsn() < more[0]);
+
+        // the records are read back in memory as well as after the next flush
+        assert_eq!(record_number(&lm.read_at(more[0]).unwrap()), 6);
+        lm.flush(more[24]).unwrap();
+        assert_eq!(lm.get_last_saved_lsn(), more[24]);
+        assert_log_records(lm.clone(), (1..=30).rev().collect());
+        let forward: Vec<u64> = lm
+            .forward_iterator(0)
+            .unwrap()
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(forward, [lsns, more].concat());
+    }
+
+    #[test]
+    fn test_log_segments() {
+        let storage = Arc::new(MemoryBackend::new());
+        let options = FileOptions {
+            log_segment_blocks: 2,
+            log_archive: Some("archive".to_string()),
+            ..FileOptions::default()
+        };
+        const NUM: u32 = 200;
+        let lsns;
+        {
+            let db = SimpleDB::open(storage.clone(), 400, 8, options.clone()).unwrap();
+            let lm = db.log_manager();
+            lsns = create_records(lm.clone(), 1, NUM);
+            lm.flush(lsns[NUM as usize - 1]).unwrap();
+
+            // the log is spread over segments and read across them either way
+            let names = lm.segment_names();
+            assert!(names.len() > 5);
+            assert_eq!(names[0], "simpledb.log.000000");
+            let files = db.file_manager().files().unwrap();
+            assert!(names.iter().all(|name| files.contains(name)));
+            assert_eq!(db.file_manager().length("simpledb.log").unwrap(), 0);
+            assert_log_records(lm.clone(), (1..=NUM).rev().collect());
+            let forward: Vec<u64> = lm
+                .forward_iterator(0)
+                .unwrap()
+                .map(|r| r.unwrap().0)
+                .collect();
+            assert_eq!(forward, lsns);
+
+            // the segments before the record needed go to the archive
+            lm.set_retention_lsn(lsns[100]).unwrap();
+            let kept = lm.segment_names();
+            assert!(kept.len() < names.len());
+            assert_eq!(lm.retention_lsn(), lsns[100]);
+            let first_kept = (lsns[100] - 1) / 400 / 2;
+            assert_eq!(kept[0], format!("simpledb.log.{:06}", first_kept));
+            let archived = storage.open_tablespace("archive").unwrap().files().unwrap();
+            for name in &names[..names.len() - kept.len()] {
+                assert!(archived.contains(name));
+                assert!(!db.file_manager().files().unwrap().contains(name));
+            }
+            let err = lm.read_at(lsns[0]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert_eq!(record_number(&lm.read_at(lsns[100]).unwrap()), 101);
+            let backward: Vec<u64> = lm.iterator().unwrap().map(|r| r.unwrap().0).collect();
+            assert!(backward.len() < NUM as usize - 100 + 20);
+            assert!(backward.contains(&lsns[100]));
+            let forward = lm.forward_iterator(0).unwrap().next().unwrap().unwrap().0;
+            assert!(forward <= lsns[100] && forward > lsns[0]);
+        }
+
+        // the kept segments are found again on open and the log carries on after them
+        let db = SimpleDB::open(storage.clone(), 400, 8, options.clone()).unwrap();
+        let lm = db.log_manager();
+        assert_eq!(lm.get_last_saved_lsn(), lsns[NUM as usize - 1]);
+        let more = create_records(lm.clone(), NUM + 1, NUM + 50);
+        assert!(more[0] > lsns[NUM as usize - 1]);
+        lm.flush(more[49]).unwrap();
+        assert_eq!(record_number(&lm.read_at(lsns[150]).unwrap()), 151);
+        drop(db);
+
+        // the segment size is fixed when the database is created
+        let options = FileOptions {
+            log_segment_blocks: 3,
+            ..options
+        };
+        let err = SimpleDB::open(storage.clone(), 400, 8, options)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SuperblockError>(),
+            Some(&SuperblockError::LogSegmentSizeMismatch {
+                stored: 2,
+                requested: 3
+            })
+        );
+        let err = SimpleDB::with_backend(storage, 400, 8).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // the archive cannot be the database directory, segments moved there would be lost
+        let dir = "./test_log_archive_location";
+        let options = FileOptions {
+            log_segment_blocks: 2,
+            log_archive: Some(".".to_string()),
+            ..FileOptions::default()
+        };
+        let err = SimpleDB::with_options(dir, 400, 8, options).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_group_commit() {
+        const THREADS: u32 = 8;
+        let options = FileOptions {
+            commit_delay: Duration::from_millis(20),
+            ..FileOptions::default()
+        };
+        let db = SimpleDB::open(Arc::new(MemoryBackend::new()), 400, 8, options).unwrap();
+        let group_commit = db.group_commit();
+        let syncs_before = db.file_manager().stats().file("simpledb.log").syncs;
+
+        let barrier = Arc::new(Barrier::new(THREADS as usize));
+        let handles: Vec<_> = (1..=THREADS)
+            .map(|i| {
+                let lm = db.log_manager();
+                let group_commit = group_commit.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let lsn = create_records(lm.clone(), i, i)[0];
+                    group_commit.commit(lsn).unwrap();
+                    // the commit returns only once the record is on disk
+                    assert!(lm.get_last_saved_lsn() >= lsn);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = group_commit.stats();
+        assert_eq!(stats.commits, THREADS as u64);
+        let released: u64 = stats.batch_sizes.iter().map(|(size, n)| size * n).sum();
+        assert_eq!(released, THREADS as u64);
+        // the committers arrived together, so they shared flushes
+        assert!(stats.flushes < THREADS as u64);
+        assert!(stats.largest_batch() > 1);
+        assert!(stats.average_batch() > 1.0);
+        let syncs = db.file_manager().stats().file("simpledb.log").syncs - syncs_before;
+        assert_eq!(syncs, stats.flushes);
+
+        // a record that is on disk already is committed without a flush
+        let lsn = create_records(db.log_manager(), 100, 100)[0];
+        db.log_manager().flush(lsn).unwrap();
+        group_commit.commit(lsn).unwrap();
+        group_commit.commit(lsn).unwrap();
+        let after = group_commit.stats();
+        assert_eq!(after.commits, stats.commits + 2);
+        assert_eq!(after.flushes, stats.flushes);
+        assert_eq!(
+            db.file_manager().stats().file("simpledb.log").syncs - syncs_before,
+            stats.flushes + 1
+        );
+        // the log holds the record made last, then one record per committer in any order
+        let mut numbers: Vec<u32> = db
+            .log_manager()
+            .iterator()
+            .unwrap()
+            .map(|r| record_number(&r.unwrap().1))
+            .collect();
+        assert_eq!(numbers.remove(0), 100);
+        numbers.sort_unstable();
+        assert_eq!(numbers, (1..=THREADS).collect::<Vec<_>>());
+    }
 
-        fs::remove_dir_all("test_log").unwrap();
+    // the number in a record made by create_records
+    fn record_number(rec: &[u8]) -> u32 {
+        let page = Page::from_bytes(rec);
+        let s = page.get_string(0).unwrap();
+        s.trim_start_matches("record: ").parse().unwrap()
     }
 
-    fn assert_log_records(lm: Arc<Mutex<LogManager>>, expected: Vec<u32>) {
-        let mut lm = lm.lock().unwrap();
-        let itr = lm.iterator().unwrap();
+    fn assert_log_records(lm: Arc<LogManager>, expected: Vec<u32>) {
+        let mut itr = lm.iterator().unwrap();
 
-        for (rec, exp) in zip(itr, expected) {
+        let mut previous = u64::MAX;
+        for exp in expected {
+            let (lsn, rec) = itr
+                .next()
+                .unwrap_or_else(|| panic!("the log ends before record {}", exp))
+                .unwrap();
+            assert!(lsn < previous);
+            previous = lsn;
             let page = Page::from_bytes(&rec);
             let s = page.get_string(0).unwrap();
             let n = Page::max_length(s.len());
@@ -37,16 +576,25 @@ mod tests {
             assert_eq!(s, format!("record: {}", exp));
             assert_eq!(val, exp + 100);
         }
+        // no more records than expected either
+        assert!(itr.next().is_none());
     }
 
-    fn create_records(lm: Arc<Mutex<LogManager>>, start: u32, end: u32) {
+    // appends the records and returns their lsns, checking that they grow with the log
+    fn create_records(lm: Arc<LogManager>, start: u32, end: u32) -> Vec<u64> {
+        let mut lsns = Vec::new();
         for i in start..=end {
             let rec_string = format!("record: {}", i);
             let rec = create_log_records(&rec_string, i + 100);
-            let lsn = lm.lock().unwrap().append(&rec).unwrap();
+            let previous = lm.latest_lsn();
+            let lsn = lm.append(&rec).unwrap();
 
-            assert_eq!(lsn, i)
+            assert!(lsn > previous);
+            // other threads may have appended records since
+            assert!(lm.latest_lsn() >= lsn);
+            lsns.push(lsn);
         }
+        lsns
     }
 
     fn create_log_records(rec_string: &str, n: u32) -> Vec<u8> {
@@ -1,9 +1,3 @@
-mod app;
-mod buffer;
-mod file;
-mod log;
-mod tx;
-
 fn main() {
     println!("Hello, world!");
 }
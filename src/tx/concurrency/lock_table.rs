@@ -22,6 +22,12 @@ pub struct LockTable {
     locks: HashMap<BlockId, i32>,
 }
 
+impl Default for LockTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LockTable {
     const MAX_TIME: u128 = 10 * 1000;
 
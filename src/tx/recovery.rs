@@ -1,6 +1,8 @@
 pub mod checkpoint_record;
 pub mod commit_record;
+pub mod log_dump;
 pub mod log_record;
+pub mod point_in_time;
 pub mod recovery_manager;
 pub mod rollback_record;
 pub mod setint_record;
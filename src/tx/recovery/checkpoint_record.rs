@@ -1,24 +1,42 @@
-use crate::tx::transaction::{Transaction, TransactionError};
+use std::fmt;
+use std::io::Error;
+use std::mem::size_of;
+
+use crate::log::log_manager::LogManager;
+use crate::{file::page::Page, tx::transaction::Transaction};
 
 use super::log_record::{LogRecord, Op};
 
+#[derive(Default)]
 pub struct CheckPointRecord {}
 impl CheckPointRecord {
     pub fn new() -> Self {
-        todo!()
+        Self {}
+    }
+
+    pub fn write_to_log(log_manager: &LogManager) -> Result<u64, Error> {
+        let mut page = Page::new(size_of::<u32>());
+        page.set_int(0, Op::CheckPoint as u32);
+
+        log_manager.append(&page.contents().to_vec())
     }
 }
 
 impl LogRecord for CheckPointRecord {
     fn op(&self) -> Op {
-        todo!()
+        Op::CheckPoint
     }
 
+    // a checkpoint belongs to no transaction
     fn tx_number(&self) -> Option<usize> {
-        todo!()
+        None
     }
 
-    fn undo(&self, tx: &mut Transaction) {
-        todo!()
+    fn undo(&self, _tx: &mut Transaction) {}
+}
+
+impl fmt::Display for CheckPointRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<CHECKPOINT>")
     }
 }
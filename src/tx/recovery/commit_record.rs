@@ -1,24 +1,65 @@
-use crate::{file::page::Page, tx::transaction::Transaction};
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::mem::size_of;
+use std::time::SystemTime;
 
-use super::log_record::{LogRecord, Op};
+use crate::log::log_manager::LogManager;
+use crate::{
+    file::page::Page,
+    tx::transaction::{Transaction, TransactionError},
+};
 
-pub struct CommitRecord {}
+use super::log_record::{read_int, LogRecord, Op, TX_POS};
+
+const TIMESTAMP_POS: usize = TX_POS + size_of::<u32>();
+
+pub struct CommitRecord {
+    tx_num: usize,
+    // None for a commit logged before commits were stamped with the time, which holds only the tx
+    timestamp: Option<SystemTime>,
+}
 impl CommitRecord {
-    pub fn new(page: Page) -> Self {
-        todo!()
+    pub fn new(page: Page) -> Result<Self, TransactionError> {
+        let tx_num = read_int(&page, TX_POS)? as usize;
+        let timestamp = if page.contents().len() > TIMESTAMP_POS {
+            Some(page.try_get_timestamp(TIMESTAMP_POS)?)
+        } else {
+            None
+        };
+        Ok(Self { tx_num, timestamp })
+    }
+
+    // the commit is stamped with the current time, see point_in_time.rs
+    pub fn write_to_log(log_manager: &LogManager, tx_num: usize) -> Result<u64, Error> {
+        let mut page = Page::new(TIMESTAMP_POS + size_of::<i64>());
+        page.set_int(0, Op::Commit as u32);
+        page.set_int(TX_POS, tx_num as u32);
+        page.try_set_timestamp(TIMESTAMP_POS, SystemTime::now())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+        log_manager.append(&page.contents().to_vec())
     }
 }
 
 impl LogRecord for CommitRecord {
     fn op(&self) -> Op {
-        todo!()
+        Op::Commit
     }
 
     fn tx_number(&self) -> Option<usize> {
-        todo!()
+        Some(self.tx_num)
+    }
+
+    fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
     }
 
-    fn undo(&self, tx: &mut Transaction) {
-        todo!()
+    // a committed transaction is never undone
+    fn undo(&self, _tx: &mut Transaction) {}
+}
+
+impl fmt::Display for CommitRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<COMMIT {}>", self.tx_num)
     }
 }
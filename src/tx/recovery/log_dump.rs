@@ -0,0 +1,161 @@
+use std::{
+    io::{Error, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    file::block_id::BlockId, log::log_manager::LogManager, tx::transaction::TransactionError,
+};
+
+use super::log_record::{create_log_record, LogRecord, LogValue};
+
+// Lists the records of a log in a readable form, see the simpledb-logdump binary.
+// Records are decoded into their op, transaction, block, offset and value; a record that cannot
+// be decoded is listed with the reason instead of ending the dump.
+
+// Which records to list; every record passes an empty filter.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    pub tx: Option<usize>,
+    pub block: Option<BlockId>,
+    // lsns of the records to list, both ends included
+    pub from_lsn: Option<u64>,
+    pub to_lsn: Option<u64>,
+}
+
+impl LogFilter {
+    pub fn matches_lsn(&self, lsn: u64) -> bool {
+        self.from_lsn.is_none_or(|from| lsn >= from) && self.to_lsn.is_none_or(|to| lsn <= to)
+    }
+
+    // a record that cannot be decoded passes only if no transaction or block is asked for
+    pub fn matches(&self, lsn: u64, record: &Result<Box<dyn LogRecord>, TransactionError>) -> bool {
+        if !self.matches_lsn(lsn) {
+            return false;
+        }
+        match record {
+            Ok(record) => {
+                self.tx.is_none_or(|tx| record.tx_number() == Some(tx))
+                    && self
+                        .block
+                        .as_ref()
+                        .is_none_or(|blk| record.block_id() == Some(blk))
+            }
+            Err(_) => self.tx.is_none() && self.block.is_none(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    // one record per line as it is displayed, after its lsn, with control characters escaped
+    Text,
+    // one JSON object per line
+    Json,
+}
+
+/**
+ * writes a line for each record of the log that passes the filter, the newest first,
+ * and returns the number of lines written. an error reading the log ends the dump.
+ */
+pub fn dump_log(
+    log_manager: &LogManager,
+    filter: &LogFilter,
+    format: DumpFormat,
+    out: &mut dyn Write,
+) -> Result<u64, Error> {
+    let mut written = 0;
+    for item in log_manager.iterator()? {
+        let (lsn, bytes) = item?;
+        // the records still to come are older
+        if filter.from_lsn.is_some_and(|from| lsn < from) {
+            break;
+        }
+        let record = create_log_record(bytes);
+        if filter.matches(lsn, &record) {
+            writeln!(out, "{}", format_record(lsn, &record, format))?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+pub fn format_record(
+    lsn: u64,
+    record: &Result<Box<dyn LogRecord>, TransactionError>,
+    format: DumpFormat,
+) -> String {
+    match (record, format) {
+        (Ok(record), DumpFormat::Text) => {
+            format!("{} {}", lsn, escape_controls(&record.to_string()))
+        }
+        (Err(e), DumpFormat::Text) => format!("{} <INVALID {}>", lsn, e),
+        (Ok(record), DumpFormat::Json) => {
+            format!(
+                "{{\"lsn\":{},\"op\":\"{}\",\"tx\":{},\"timestamp\":{},\"file\":{},\"block\":{},\"offset\":{},\"value\":{},\"new_value\":{}}}",
+                lsn,
+                record.op(),
+                json_or_null(record.tx_number()),
+                json_or_null(record.timestamp().map(micros_since_epoch)),
+                json_or_null(record.block_id().map(|blk| json_string(blk.filename()))),
+                json_or_null(record.block_id().map(|blk| blk.number())),
+                json_or_null(record.offset()),
+                json_value(record.value()),
+                json_value(record.new_value())
+            )
+        }
+        (Err(e), DumpFormat::Json) => format!(
+            "{{\"lsn\":{},\"error\":{}}}",
+            lsn,
+            json_string(&e.to_string())
+        ),
+    }
+}
+
+// keeps a record on one line even if a string in it has line breaks
+fn escape_controls(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            c if c.is_control() => c.escape_default().to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// a timestamp is given as microseconds since the unix epoch, the way it is stored
+fn micros_since_epoch(t: SystemTime) -> i64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_micros() as i64,
+        Err(e) => -(e.duration().as_micros() as i64),
+    }
+}
+
+fn json_value(value: Option<LogValue>) -> String {
+    match value {
+        Some(LogValue::Int(n)) => n.to_string(),
+        Some(LogValue::String(s)) => json_string(&s),
+        None => "null".to_string(),
+    }
+}
+
+fn json_or_null(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
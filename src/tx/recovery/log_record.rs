@@ -1,5 +1,12 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    mem::size_of,
+    time::SystemTime,
+};
+
 use crate::{
-    file::page::Page,
+    file::{block_id::BlockId, file_manager::FileManager, page::Page},
     tx::transaction::{Transaction, TransactionError},
 };
 
@@ -9,6 +16,14 @@ use super::{
     setstring_record::SetStringRecord, start_record::StartRecord,
 };
 
+// Every log record starts with its op; all but a checkpoint go on with the number of the
+// transaction that wrote it. A commit goes on with the time it was made. An update goes on with
+// the block it changed, as its file name and block number, the offset in the block, the value
+// found there before the change, to undo it, and the value written, to redo it:
+// | op (u32) | tx (u32) | file name (string) | block (u32) | offset (u32) | old value | new value |
+pub const TX_POS: usize = size_of::<u32>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Op {
     CheckPoint = 0,
     Start = 1,
@@ -33,21 +48,154 @@ impl TryFrom<u32> for Op {
     }
 }
 
-pub trait LogRecord {
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Op::CheckPoint => "CHECKPOINT",
+            Op::Start => "START",
+            Op::Commit => "COMMIT",
+            Op::Rollback => "ROLLBACK",
+            Op::SetInt => "SETINT",
+            Op::SetString => "SETSTRING",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The value an update record holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogValue {
+    Int(u32),
+    String(String),
+}
+
+impl fmt::Display for LogValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogValue::Int(n) => write!(f, "{}", n),
+            LogValue::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+pub trait LogRecord: fmt::Display {
     fn op(&self) -> Op;
     fn tx_number(&self) -> Option<usize>;
+    // the block an update changed, None for the other records
+    fn block_id(&self) -> Option<&BlockId> {
+        None
+    }
+    // where in the block an update changed the value
+    fn offset(&self) -> Option<usize> {
+        None
+    }
+    // the value an update found before it changed it
+    fn value(&self) -> Option<LogValue> {
+        None
+    }
+    // the value an update wrote
+    fn new_value(&self) -> Option<LogValue> {
+        None
+    }
+    // when a commit was made
+    fn timestamp(&self) -> Option<SystemTime> {
+        None
+    }
     fn undo(&self, tx: &mut Transaction);
+    // writes the new value of an update to its block again, to replay the log record at the lsn
+    fn redo(&self, _file_manager: &FileManager, _lsn: u64) -> Result<(), Error> {
+        Ok(())
+    }
+    /**
+     * writes the old value of an update back to its block, outside of any transaction.
+     * the block is stamped with the lsn of the record the undo is done for.
+     */
+    fn revert(&self, _file_manager: &FileManager, _lsn: u64) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 pub fn create_log_record(rec: Vec<u8>) -> Result<Box<dyn LogRecord>, TransactionError> {
     let page = Page::from_bytes(&rec);
-    match page.get_int(0).try_into() {
+    let op = read_int(&page, 0)?;
+    match op.try_into() {
         Ok(Op::CheckPoint) => Ok(Box::new(CheckPointRecord::new())),
-        Ok(Op::Start) => Ok(Box::new(StartRecord::new(page))),
-        Ok(Op::Commit) => Ok(Box::new(CommitRecord::new(page))),
-        Ok(Op::Rollback) => Ok(Box::new(RollbackRecord::new(page))),
-        Ok(Op::SetInt) => Ok(Box::new(SetIntRecord::new(page))),
+        Ok(Op::Start) => Ok(Box::new(StartRecord::new(page)?)),
+        Ok(Op::Commit) => Ok(Box::new(CommitRecord::new(page)?)),
+        Ok(Op::Rollback) => Ok(Box::new(RollbackRecord::new(page)?)),
+        Ok(Op::SetInt) => Ok(Box::new(SetIntRecord::new(page)?)),
         Ok(Op::SetString) => Ok(Box::new(SetStringRecord::new(page)?)),
-        Err(_) => Err(TransactionError::General),
+        Err(_) => Err(TransactionError::UnknownOp(op)),
     }
 }
+
+// reads an int of a record, failing rather than panicking if the record is too short for it
+pub fn read_int(page: &Page, offset: usize) -> Result<u32, TransactionError> {
+    Ok(page.try_get_u32(offset)?)
+}
+
+// reads a string written with Page::set_string, failing if the record is too short for it
+pub fn read_string(page: &Page, offset: usize) -> Result<String, TransactionError> {
+    Ok(page.try_get_string(offset)?)
+}
+
+/**
+ * reads the block and offset of an update record.
+ * returns them with the position of the value that follows.
+ */
+pub fn read_target(page: &Page) -> Result<(BlockId, usize, usize), TransactionError> {
+    let f_pos = TX_POS + size_of::<u32>();
+    let file_name = read_string(page, f_pos)?;
+    let b_pos = f_pos + Page::max_length(file_name.len());
+    let block_id = BlockId::new(&file_name, read_int(page, b_pos)?.into());
+    let o_pos = b_pos + size_of::<u32>();
+    let offset = read_int(page, o_pos)? as usize;
+    Ok((block_id, offset, o_pos + size_of::<u32>()))
+}
+
+/**
+ * lays out the head of an update record in a page large enough for a value of value_len bytes.
+ * returns the page with the position the value goes to.
+ */
+pub fn write_target(
+    op: Op,
+    tx_num: usize,
+    block_id: &BlockId,
+    offset: usize,
+    value_len: usize,
+) -> (Page, usize) {
+    let f_pos = TX_POS + size_of::<u32>();
+    let b_pos = f_pos + Page::max_length(block_id.filename().len());
+    let o_pos = b_pos + size_of::<u32>();
+    let v_pos = o_pos + size_of::<u32>();
+
+    let mut page = Page::new(v_pos + value_len);
+    page.set_int(0, op as u32);
+    page.set_int(TX_POS, tx_num as u32);
+    page.set_string(f_pos, block_id.filename());
+    page.set_int(b_pos, block_id.number() as u32);
+    page.set_int(o_pos, offset as u32);
+    (page, v_pos)
+}
+
+/**
+ * writes the value to the block on disk directly, for replaying the log outside of transactions.
+ * the block gets the lsn of the record replayed, as it would have in the buffer pool.
+ */
+pub fn write_value(
+    file_manager: &FileManager,
+    block_id: &BlockId,
+    offset: usize,
+    value: &LogValue,
+    lsn: u64,
+) -> Result<(), Error> {
+    let mut page = Page::new(file_manager.block_size());
+    file_manager.read(block_id, &mut page)?;
+    let written = match value {
+        LogValue::Int(n) => page.try_set_u32(offset, *n),
+        LogValue::String(s) => page.try_set_string(offset, s),
+    };
+    written.map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", block_id, e)))?;
+    page.set_lsn(lsn);
+    file_manager.write(block_id, &mut page)
+}
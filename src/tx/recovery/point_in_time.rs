@@ -0,0 +1,121 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Error, ErrorKind},
+    time::SystemTime,
+};
+
+use crate::{file::file_manager::FileManager, log::log_manager::LogManager};
+
+use super::{
+    log_record::{create_log_record, LogRecord, Op},
+    rollback_record::RollbackRecord,
+};
+
+// Brings the files of a database back to how they were at a point in the past.
+// The files are restored from a base backup taken earlier, and the log, which has to reach back
+// to before the backup, e.g. with the segments archived since brought back, is replayed forward
+// over them: every update writes its new value again. Replay stops at the target; the log is cut
+// off there, and the transactions that had not committed by then are rolled back by writing
+// their old values back, newest first, and logging a rollback for each.
+// As updates write whole values, replaying one the backup already holds does no harm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryTarget {
+    // replays the records up to and including the one with this lsn
+    Lsn(u64),
+    // replays the records before the first commit made after this time
+    Time(SystemTime),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    // segments brought back from the log archive
+    pub restored_segments: Vec<String>,
+    pub records_replayed: u64,
+    // lsn of the last record replayed, where the log was cut off; 0 if there was none
+    pub end_lsn: u64,
+    // transactions that committed before the target
+    pub committed: Vec<usize>,
+    // transactions in flight at the target, which were rolled back
+    pub rolled_back: Vec<usize>,
+}
+
+impl RecoveryTarget {
+    // whether replay stops before the record with the lsn
+    fn stops_at(&self, lsn: u64, record: &dyn LogRecord) -> bool {
+        match self {
+            RecoveryTarget::Lsn(target) => lsn > *target,
+            RecoveryTarget::Time(target) => record.timestamp().is_some_and(|t| t > *target),
+        }
+    }
+}
+
+/**
+ * replays the log over the files from its start up to the target, cuts the log off there and
+ * rolls back the transactions in flight at that point. nothing else may use the database
+ * meanwhile. fails with InvalidData if a record cannot be decoded.
+ */
+pub fn replay_to(
+    file_manager: &FileManager,
+    log_manager: &LogManager,
+    target: RecoveryTarget,
+) -> Result<RecoveryReport, Error> {
+    let mut report = RecoveryReport::default();
+    // updates of the transactions in flight, the oldest first
+    let mut in_flight: BTreeMap<usize, Vec<Box<dyn LogRecord>>> = BTreeMap::new();
+    let mut written = BTreeSet::new();
+    for item in log_manager.forward_iterator(0)? {
+        let (lsn, bytes) = item?;
+        let record = create_log_record(bytes).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("cannot replay the log record at lsn {}: {}", lsn, e),
+            )
+        })?;
+        if target.stops_at(lsn, record.as_ref()) {
+            break;
+        }
+        match (record.op(), record.tx_number()) {
+            (Op::Start, Some(tx)) => {
+                in_flight.entry(tx).or_default();
+            }
+            (Op::SetInt | Op::SetString, Some(tx)) => {
+                record.redo(file_manager, lsn)?;
+                written.extend(record.block_id().map(|blk| blk.filename().to_string()));
+                in_flight.entry(tx).or_default().push(record);
+            }
+            (Op::Commit, Some(tx)) => {
+                in_flight.remove(&tx);
+                report.committed.push(tx);
+            }
+            // the undo of a rollback is not logged, so it is done again here
+            (Op::Rollback, Some(tx)) => {
+                for update in in_flight.remove(&tx).unwrap_or_default().iter().rev() {
+                    update.revert(file_manager, lsn)?;
+                }
+            }
+            _ => {}
+        }
+        report.records_replayed += 1;
+        report.end_lsn = lsn;
+    }
+
+    // the rollback records are only written once the log is cut off, the blocks get the lsn
+    // the log ends at until then
+    for (tx, updates) in &in_flight {
+        for update in updates.iter().rev() {
+            update.revert(file_manager, report.end_lsn)?;
+        }
+        report.rolled_back.push(*tx);
+    }
+    for filename in written {
+        file_manager.sync(&filename)?;
+    }
+    // the records past the target belong to a history that is gone now
+    log_manager.truncate_after(report.end_lsn)?;
+    let mut lsn = 0;
+    for tx in &report.rolled_back {
+        lsn = RollbackRecord::write_to_log(log_manager, *tx)?;
+    }
+    log_manager.flush(lsn)?;
+    Ok(report)
+}
@@ -1,27 +1,115 @@
+use std::fmt;
+use std::io::Error;
+use std::mem::size_of;
+
+use crate::log::log_manager::LogManager;
 use crate::{
-    file::page::Page,
+    file::{block_id::BlockId, file_manager::FileManager, page::Page},
     tx::transaction::{Transaction, TransactionError},
 };
 
-use super::log_record::{LogRecord, Op};
+use super::log_record::{
+    read_int, read_target, write_target, write_value, LogRecord, LogValue, Op, TX_POS,
+};
 
-pub struct SetIntRecord {}
+pub struct SetIntRecord {
+    tx_num: usize,
+    offset: usize,
+    val: u32,
+    new_val: u32,
+    block_id: BlockId,
+}
 impl SetIntRecord {
-    pub fn new(page: Page) -> Self {
-        todo!()
+    pub fn new(page: Page) -> Result<Self, TransactionError> {
+        let tx_num = read_int(&page, TX_POS)? as usize;
+        let (block_id, offset, v_pos) = read_target(&page)?;
+        let val = read_int(&page, v_pos)?;
+        let new_val = read_int(&page, v_pos + size_of::<u32>())?;
+
+        Ok(Self {
+            tx_num,
+            offset,
+            val,
+            new_val,
+            block_id,
+        })
+    }
+
+    pub fn write_to_log(
+        log_manager: &LogManager,
+        tx_num: usize,
+        block_id: &BlockId,
+        offset: usize,
+        val: u32,
+        new_val: u32,
+    ) -> Result<u64, Error> {
+        let (mut page, v_pos) =
+            write_target(Op::SetInt, tx_num, block_id, offset, 2 * size_of::<u32>());
+        page.set_int(v_pos, val);
+        page.set_int(v_pos + size_of::<u32>(), new_val);
+
+        log_manager.append(&page.contents().to_vec())
     }
 }
 
 impl LogRecord for SetIntRecord {
     fn op(&self) -> Op {
-        todo!()
+        Op::SetInt
     }
 
     fn tx_number(&self) -> Option<usize> {
-        todo!()
+        Some(self.tx_num)
+    }
+
+    fn block_id(&self) -> Option<&BlockId> {
+        Some(&self.block_id)
+    }
+
+    fn offset(&self) -> Option<usize> {
+        Some(self.offset)
+    }
+
+    fn value(&self) -> Option<LogValue> {
+        Some(LogValue::Int(self.val))
+    }
+
+    fn new_value(&self) -> Option<LogValue> {
+        Some(LogValue::Int(self.new_val))
     }
 
     fn undo(&self, tx: &mut Transaction) {
-        todo!()
+        tx.pin(&self.block_id);
+        tx.set_int(&self.block_id, self.offset, self.val, false); // false: do not log
+        tx.unpin(&self.block_id);
+    }
+
+    fn redo(&self, file_manager: &FileManager, lsn: u64) -> Result<(), Error> {
+        write_value(
+            file_manager,
+            &self.block_id,
+            self.offset,
+            &LogValue::Int(self.new_val),
+            lsn,
+        )
+    }
+
+    fn revert(&self, file_manager: &FileManager, lsn: u64) -> Result<(), Error> {
+        write_value(
+            file_manager,
+            &self.block_id,
+            self.offset,
+            &LogValue::Int(self.val),
+            lsn,
+        )
+    }
+}
+
+impl fmt::Display for SetIntRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<SETINT {} {} {} {} {}>",
+            self.tx_num, self.block_id, self.offset, self.val, self.new_val
+        )
     }
 }
@@ -1,27 +1,48 @@
+use std::fmt;
+use std::io::Error;
+use std::mem::size_of;
+
+use crate::log::log_manager::LogManager;
 use crate::{
     file::page::Page,
     tx::transaction::{Transaction, TransactionError},
 };
 
-use super::log_record::{LogRecord, Op};
+use super::log_record::{read_int, LogRecord, Op, TX_POS};
 
-pub struct StartRecord {}
+pub struct StartRecord {
+    tx_num: usize,
+}
 impl StartRecord {
-    pub fn new(page: Page) -> Self {
-        todo!()
+    pub fn new(page: Page) -> Result<Self, TransactionError> {
+        let tx_num = read_int(&page, TX_POS)? as usize;
+        Ok(Self { tx_num })
+    }
+
+    pub fn write_to_log(log_manager: &LogManager, tx_num: usize) -> Result<u64, Error> {
+        let mut page = Page::new(TX_POS + size_of::<u32>());
+        page.set_int(0, Op::Start as u32);
+        page.set_int(TX_POS, tx_num as u32);
+
+        log_manager.append(&page.contents().to_vec())
     }
 }
 
 impl LogRecord for StartRecord {
     fn op(&self) -> Op {
-        todo!()
+        Op::Start
     }
 
     fn tx_number(&self) -> Option<usize> {
-        todo!()
+        Some(self.tx_num)
     }
 
-    fn undo(&self, tx: &mut Transaction) {
-        todo!()
+    // nothing was changed yet
+    fn undo(&self, _tx: &mut Transaction) {}
+}
+
+impl fmt::Display for StartRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<START {}>", self.tx_num)
     }
 }
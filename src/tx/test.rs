@@ -1,16 +1,36 @@
 #[cfg(test)]
 mod tests {
     use std::{
-        fs,
         iter::zip,
         mem::size_of,
         sync::{Arc, Mutex},
     };
 
     use crate::{app::simple_db::SimpleDB, file::block_id::BlockId, tx::transaction::Transaction};
+    use crate::{
+        file::{file_manager::FileManager, options::FileOptions, page::Page},
+        tx::{
+            recovery::{
+                checkpoint_record::CheckPointRecord,
+                commit_record::CommitRecord,
+                log_dump::{dump_log, DumpFormat, LogFilter},
+                log_record::{create_log_record, LogValue, Op},
+                point_in_time::RecoveryTarget,
+                rollback_record::RollbackRecord,
+                setint_record::SetIntRecord,
+                setstring_record::SetStringRecord,
+                start_record::StartRecord,
+            },
+            transaction::TransactionError,
+        },
+    };
+    use std::{env, fs, io::ErrorKind, path::PathBuf, thread, time::Duration};
+
+    use crate::file::storage::fs_backend::FsBackend;
+
     #[test]
     fn test_transaction() {
-        let db = SimpleDB::new("./test_transaction", 400, 8).unwrap();
+        let db = SimpleDB::new_in_memory(400, 8).unwrap();
         let mut tx1 = Transaction::new(
             db.file_manager().clone(),
             db.log_manager().clone(),
@@ -67,7 +87,321 @@ mod tests {
         println!("post rollback");
         assert_eq!(tx4.get_int(&blk, 80), 2);
         tx4.commit();
+    }
+
+    #[test]
+    fn test_log_records() {
+        let db = SimpleDB::new_in_memory(400, 8).unwrap();
+        let lm = db.log_manager();
+        let blk = BlockId::new("testfile", 7);
+        StartRecord::write_to_log(&lm, 1).unwrap();
+        SetIntRecord::write_to_log(&lm, 1, &blk, 80, 42, 43).unwrap();
+        SetStringRecord::write_to_log(&lm, 1, &blk, 40, "one", "two").unwrap();
+        CommitRecord::write_to_log(&lm, 1).unwrap();
+        RollbackRecord::write_to_log(&lm, 2).unwrap();
+        CheckPointRecord::write_to_log(&lm).unwrap();
+
+        let records: Vec<_> = lm
+            .iterator()
+            .unwrap()
+            .map(|r| create_log_record(r.unwrap().1).ok().unwrap())
+            .collect();
+        let ops: Vec<Op> = records.iter().map(|r| r.op()).collect();
+        assert_eq!(
+            ops,
+            vec![
+                Op::CheckPoint,
+                Op::Rollback,
+                Op::Commit,
+                Op::SetString,
+                Op::SetInt,
+                Op::Start
+            ]
+        );
+        let txs: Vec<Option<usize>> = records.iter().map(|r| r.tx_number()).collect();
+        assert_eq!(txs, vec![None, Some(2), Some(1), Some(1), Some(1), Some(1)]);
+
+        // the old and the new value of an update follow its block and offset
+        let set_string = &records[3];
+        assert_eq!(set_string.block_id(), Some(&blk));
+        assert_eq!(set_string.offset(), Some(40));
+        assert_eq!(
+            set_string.value(),
+            Some(LogValue::String("one".to_string()))
+        );
+        assert_eq!(
+            set_string.new_value(),
+            Some(LogValue::String("two".to_string()))
+        );
+        let set_int = &records[4];
+        assert_eq!(set_int.block_id(), Some(&blk));
+        assert_eq!(set_int.offset(), Some(80));
+        assert_eq!(set_int.value(), Some(LogValue::Int(42)));
+        assert_eq!(set_int.new_value(), Some(LogValue::Int(43)));
+        assert!(records[2].timestamp().is_some());
+        assert_eq!(set_int.timestamp(), None);
+        // a commit logged before commits were stamped has no timestamp
+        let unstamped = create_log_record(vec![Op::Commit as u8, 0, 0, 0, 1, 0, 0, 0])
+            .ok()
+            .unwrap();
+        assert_eq!(unstamped.tx_number(), Some(1));
+        assert_eq!(unstamped.timestamp(), None);
+        assert_eq!(records[0].block_id(), None);
+        assert_eq!(records[5].to_string(), "<START 1>");
+        assert_eq!(records[0].to_string(), "<CHECKPOINT>");
+
+        // records that are not what they claim to be fail to decode instead of panicking
+        assert!(matches!(
+            create_log_record(vec![9, 0, 0, 0]),
+            Err(TransactionError::UnknownOp(9))
+        ));
+        assert!(matches!(
+            create_log_record(vec![Op::SetInt as u8, 0, 0, 0, 1, 0, 0, 0, 200, 0, 0, 0]),
+            Err(TransactionError::PageError(_))
+        ));
+        assert!(matches!(
+            create_log_record(vec![Op::Start as u8]),
+            Err(TransactionError::PageError(_))
+        ));
+    }
+
+    #[test]
+    fn test_log_dump() {
+        let dir = "test_log_dump";
+        let _ = fs::remove_dir_all(dir);
+        let blk = BlockId::new("testfile", 3);
+        let other = BlockId::new("testfile", 4);
+        let (set_lsn, commit_lsn) = {
+            let db = SimpleDB::new(dir, 400, 8).unwrap();
+            let lm = db.log_manager();
+            StartRecord::write_to_log(&lm, 1).unwrap();
+            StartRecord::write_to_log(&lm, 2).unwrap();
+            let set_lsn = SetIntRecord::write_to_log(&lm, 1, &blk, 80, 5, 6).unwrap();
+            SetStringRecord::write_to_log(&lm, 2, &other, 0, "", "a \"quoted\"\nline").unwrap();
+            let commit_lsn = CommitRecord::write_to_log(&lm, 1).unwrap();
+            lm.append(&vec![42, 0, 0, 0]).unwrap();
+            lm.flush(lm.latest_lsn()).unwrap();
+            (set_lsn, commit_lsn)
+        };
+        let files = |dir| {
+            let mut files: Vec<_> = fs::read_dir(dir)
+                .unwrap()
+                .map(|e| {
+                    let e = e.unwrap();
+                    (e.file_name(), e.metadata().unwrap().len())
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        let before = files(dir);
+
+        let db = SimpleDB::open_read_only(dir, 1, FileOptions::default()).unwrap();
+        let dump = |filter: &LogFilter, format| {
+            let mut out = Vec::new();
+            dump_log(&db.log_manager(), filter, format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let all = dump(&LogFilter::default(), DumpFormat::Text);
+        let lines: Vec<&str> = all.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].ends_with("<INVALID unknown log record type 42>"));
+        assert_eq!(lines[1], format!("{} <COMMIT 1>", commit_lsn));
+        assert!(lines[2].ends_with(" 0  a \"quoted\"\\nline>"));
+        assert!(lines[5].ends_with("<START 1>"));
+
+        let by_tx = dump(
+            &LogFilter {
+                tx: Some(1),
+                ..LogFilter::default()
+            },
+            DumpFormat::Text,
+        );
+        assert_eq!(by_tx.lines().count(), 3);
+
+        let by_block = dump(
+            &LogFilter {
+                block: Some(blk.clone()),
+                ..LogFilter::default()
+            },
+            DumpFormat::Json,
+        );
+        assert_eq!(
+            by_block,
+            format!(
+                "{{\"lsn\":{},\"op\":\"SETINT\",\"tx\":1,\"timestamp\":null,\"file\":\"testfile\",\"block\":3,\"offset\":80,\"value\":5,\"new_value\":6}}\n",
+                set_lsn
+            )
+        );
+
+        let by_lsn = dump(
+            &LogFilter {
+                from_lsn: Some(set_lsn),
+                to_lsn: Some(commit_lsn - 1),
+                ..LogFilter::default()
+            },
+            DumpFormat::Json,
+        );
+        let lines: Vec<&str> = by_lsn.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"new_value\":\"a \\\"quoted\\\"\\nline\""));
+        assert!(lines[1].contains("\"op\":\"SETINT\""));
+
+        // nothing can be written, and nothing was
+        let err = db
+            .log_manager()
+            .append(&vec![0; 8])
+            .and_then(|lsn| db.log_manager().flush(lsn));
+        assert_eq!(err.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        drop(db);
+        assert_eq!(files(dir), before);
+
+        assert_eq!(
+            SimpleDB::open_read_only("test_log_dump_missing", 1, FileOptions::default())
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::NotFound
+        );
+        assert!(fs::metadata("test_log_dump_missing").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_point_in_time_recovery() {
+        let (dir, archive, backup) = ("test_pitr", "test_pitr_archive", "test_pitr_backup");
+        let (by_lsn, by_time) = ("test_pitr_by_lsn", "test_pitr_by_time");
+        for d in [dir, archive, backup, by_lsn, by_time] {
+            let _ = fs::remove_dir_all(d);
+        }
+        // the archive is shared by the databases recovered in other directories
+        let archive_location = env::current_dir().unwrap().join(archive);
+        let options = || FileOptions {
+            log_segment_blocks: 2,
+            log_archive: Some(archive_location.display().to_string()),
+            ..FileOptions::default()
+        };
+        let blk = BlockId::new("data", 0);
+        let read_int = |fm: &FileManager, offset| {
+            let mut page = Page::new(fm.block_size());
+            fm.read(&blk, &mut page).unwrap();
+            page.get_int(offset)
+        };
+        // what a transaction does for an update: log it, then change the block
+        let update = |db: &SimpleDB, tx, offset, new_val| {
+            let fm = db.file_manager();
+            let mut page = Page::new(fm.block_size());
+            fm.read(&blk, &mut page).unwrap();
+            let old_val = page.get_int(offset);
+            let lm = db.log_manager();
+            let lsn = SetIntRecord::write_to_log(&lm, tx, &blk, offset, old_val, new_val).unwrap();
+            lm.flush(lsn).unwrap();
+            page.set_int(offset, new_val);
+            fm.write(&blk, &mut page).unwrap();
+            lsn
+        };
+        // copies the log segments, or every other file
+        let copy_files = |from: &str, to: &str, log: bool| {
+            fs::create_dir_all(to).unwrap();
+            for entry in fs::read_dir(from).unwrap() {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                if name.starts_with("simpledb.log") == log {
+                    fs::copy(format!("{}/{}", from, name), format!("{}/{}", to, name)).unwrap();
+                }
+            }
+        };
+
+        let (update_lsn, commit_time) = {
+            let db = SimpleDB::with_options(dir, 400, 8, options()).unwrap();
+            let lm = db.log_manager();
+            StartRecord::write_to_log(&lm, 1).unwrap();
+            update(&db, 1, 80, 1);
+            CommitRecord::write_to_log(&lm, 1).unwrap();
+            copy_files(dir, backup, false);
+
+            // enough transactions for the segments of the backup time to be archived
+            for tx in 10..30 {
+                StartRecord::write_to_log(&lm, tx).unwrap();
+                CommitRecord::write_to_log(&lm, tx).unwrap();
+            }
+            lm.flush(lm.latest_lsn()).unwrap();
+            lm.set_retention_lsn(lm.latest_lsn()).unwrap();
+
+            StartRecord::write_to_log(&lm, 2).unwrap();
+            update(&db, 2, 80, 2);
+            let commit_lsn = CommitRecord::write_to_log(&lm, 2).unwrap();
+            let commit_time = create_log_record(lm.read_at(commit_lsn).unwrap())
+                .ok()
+                .unwrap()
+                .timestamp()
+                .unwrap();
+            thread::sleep(Duration::from_millis(5));
+            StartRecord::write_to_log(&lm, 3).unwrap();
+            let update_lsn = update(&db, 3, 84, 7);
+            StartRecord::write_to_log(&lm, 4).unwrap();
+            update(&db, 4, 80, 3);
+            CommitRecord::write_to_log(&lm, 4).unwrap();
+            lm.flush(lm.latest_lsn()).unwrap();
+            assert_eq!(read_int(&db.file_manager(), 80), 3);
+            (update_lsn, commit_time)
+        };
+        assert!(fs::read_dir(archive).unwrap().count() > 0);
+
+        // up to the update of transaction 3, which is rolled back, before transaction 4 started
+        copy_files(backup, by_lsn, false);
+        copy_files(dir, by_lsn, true);
+        let storage = Arc::new(FsBackend::new(PathBuf::from(by_lsn)).unwrap());
+        let (db, report) =
+            SimpleDB::recover_to(storage, 400, 8, options(), RecoveryTarget::Lsn(update_lsn))
+                .unwrap();
+        assert!(!report.restored_segments.is_empty());
+        assert_eq!(report.end_lsn, update_lsn);
+        assert_eq!(report.rolled_back, vec![3]);
+        assert!(report.committed.contains(&1) && report.committed.contains(&2));
+        assert_eq!(read_int(&db.file_manager(), 80), 2);
+        assert_eq!(read_int(&db.file_manager(), 84), 0);
+        // the replayed block carries the lsn the log was cut off at
+        let mut page = Page::new(db.file_manager().block_size());
+        db.file_manager().read(&blk, &mut page).unwrap();
+        assert_eq!(page.lsn(), update_lsn);
+        // the log ends with the rollback, what came after the target is gone
+        let records: Vec<_> = db
+            .log_manager()
+            .iterator()
+            .unwrap()
+            .map(|r| create_log_record(r.unwrap().1).ok().unwrap())
+            .collect();
+        assert_eq!(records[0].op(), Op::Rollback);
+        assert_eq!(records[0].tx_number(), Some(3));
+        assert!(records.iter().all(|r| r.tx_number() != Some(4)));
+        // a commit after the recovery waits until its record is on disk
+        let lm = db.log_manager();
+        StartRecord::write_to_log(&lm, 5).unwrap();
+        let commit_lsn = CommitRecord::write_to_log(&lm, 5).unwrap();
+        db.group_commit().commit(commit_lsn).unwrap();
+        assert!(lm.get_last_saved_lsn() >= commit_lsn);
+        drop(db);
+
+        // up to the commit of transaction 2: transaction 4 had not committed yet either
+        copy_files(backup, by_time, false);
+        copy_files(dir, by_time, true);
+        let storage = Arc::new(FsBackend::new(PathBuf::from(by_time)).unwrap());
+        let (db, report) = SimpleDB::recover_to(
+            storage,
+            400,
+            8,
+            options(),
+            RecoveryTarget::Time(commit_time),
+        )
+        .unwrap();
+        assert_eq!(report.rolled_back, vec![3, 4]);
+        assert_eq!(read_int(&db.file_manager(), 80), 2);
+        assert_eq!(read_int(&db.file_manager(), 84), 0);
+        drop(db);
 
-        fs::remove_dir_all("./test_transaction").unwrap();
+        for d in [dir, archive, backup, by_lsn, by_time] {
+            fs::remove_dir_all(d).unwrap();
+        }
     }
 }
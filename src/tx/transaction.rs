@@ -1,22 +1,28 @@
 use std::{
+    fmt,
     string::FromUtf8Error,
     sync::{Arc, Mutex},
 };
 
 use crate::{
     buffer::buffer_manager::BufferManager,
-    file::{block_id::BlockId, file_manager::FileManager},
+    file::{block_id::BlockId, file_manager::FileManager, page::PageError},
     log::log_manager::LogManager,
 };
 
 pub struct Transaction {
     file_manager: Arc<FileManager>,
-    log_manager: Arc<Mutex<LogManager>>,
+    log_manager: Arc<LogManager>,
     buffer_manager: Arc<Mutex<BufferManager>>,
 }
 
+#[derive(Debug)]
 pub enum TransactionError {
     FromUtf8Error(FromUtf8Error),
+    // a log record is too short for what it claims to hold
+    PageError(PageError),
+    // a log record starts with an op that does not exist
+    UnknownOp(u32),
     General,
 }
 
@@ -26,13 +32,30 @@ impl From<FromUtf8Error> for TransactionError {
     }
 }
 
+impl From<PageError> for TransactionError {
+    fn from(e: PageError) -> Self {
+        Self::PageError(e)
+    }
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::FromUtf8Error(e) => write!(f, "invalid string: {}", e),
+            TransactionError::PageError(e) => write!(f, "invalid log record: {}", e),
+            TransactionError::UnknownOp(op) => write!(f, "unknown log record type {}", op),
+            TransactionError::General => write!(f, "transaction failed"),
+        }
+    }
+}
+
 // Provide Transactin manage for clients.
 // Ensuring that all tranzaction are serializable,recoverable and in general satisfy ACID
 
 impl Transaction {
     pub fn new(
         file_manager: Arc<FileManager>,
-        log_manager: Arc<Mutex<LogManager>>,
+        log_manager: Arc<LogManager>,
         buffer_manager: Arc<Mutex<BufferManager>>,
     ) -> Self {
         Self {